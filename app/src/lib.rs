@@ -2,19 +2,25 @@
 #![allow(clippy::needless_lifetimes)]
 
 use codee::string::FromToStringCodec;
+use engine::frequency_response::StabilityMargins;
+use engine::NiceFloat;
 use leptos::*;
 use leptos_use::signal_debounced;
 use leptos_use::storage::use_local_storage;
+use ndarray::{Array1, Array2};
+use std::rc::Rc;
 use web_sys::Event;
 
 use storage::StorageSidebar;
-use svg_plot::SVGPlot;
+use svg_plot::{Marker, Point, SVGPlot};
 
+use crate::svg_pz_map::SVGPzMap;
 use crate::svg_system_diagram::SVGSystemDiagram;
 
 mod js_types;
 mod storage;
 mod svg_plot;
+mod svg_pz_map;
 mod svg_system_diagram;
 
 struct ExecEnv {}
@@ -105,11 +111,113 @@ pub fn OutputElement(element: interpreter::execution::Output) -> impl IntoView {
             {
                 use interpreter::execution::Output::*;
             match element {
-                Err(e) => view!{ <span class="error"> { format!("{e:?}") } </span> }.into_view(),
+                Err(e) => view!{ <span class="error"> { e.to_string() } </span> }.into_view(),
                 Text(t) => t.trim_end().to_string().into_view(),
                 Plot(data) => view!{ <SVGPlot data={move || data.clone()} initial_height=300.0 /> },
+                TimePlot { time, data } => view!{
+                    <SVGPlot data={move || data.clone()} x=time x_label="time" initial_height=300.0 />
+                },
+                BodePlot { frequency, magnitude, phase, margins } => {
+                    let (gain_markers, phase_markers) = margin_markers(margins, &frequency, &phase);
+                    view!{
+                        <SVGPlot data={move || magnitude.clone()} x=frequency.clone() log_x=true
+                            y_label="magnitude [dB]" markers=gain_markers initial_height=200.0 />
+                        <SVGPlot data={move || phase.clone()} x=frequency log_x=true
+                            x_label="frequency [rad/s]" y_label="phase [deg]" markers=phase_markers
+                            initial_height=200.0 />
+                    }.into_view()
+                }
+                PoleZeroMap { poles, zeros, discrete } => view!{
+                    <SVGPzMap poles zeros discrete initial_height=300.0 />
+                },
+                RootLocusPlot { locus, poles, zeros, discrete } => view!{
+                    <SVGPzMap poles zeros discrete locus initial_height=300.0 />
+                },
+                NyquistPlot { real, imag, stability } => {
+                    let curve = Rc::new(ndarray::stack![ndarray::Axis(0), *real, *imag]);
+                    let critical = Point { x: -1.0, y: 0.0, label: "-1".into() };
+                    view!{
+                        <SVGPlot data={move || curve.clone()} xy=true arrows=true points=vec![critical]
+                            x_label="real" y_label="imaginary" initial_height=300.0 />
+                        {stability.to_string().trim_end().to_string()}
+                    }.into_view()
+                }
                 System(sys) => view!{ <SVGSystemDiagram sys=sys.clone() /> },
             } }
         </div>
     }
 }
+
+/// Markers of the gain margin in the magnitude plot and of the phase margin
+/// in the phase plot
+fn margin_markers(
+    margins: Option<StabilityMargins>,
+    frequency: &Array1<f64>,
+    phase: &Array2<f64>,
+) -> (Vec<Marker>, Vec<Marker>) {
+    let Some(margins) = margins else {
+        return (vec![], vec![]);
+    };
+    let mut gain_markers = vec![];
+    // a crossover at zero frequency is off the logarithmic axis,
+    // so it is marked at the lowest plotted frequency
+    let phase_crossover = if margins.phase_crossover > 0.0 {
+        Some(margins.phase_crossover)
+    } else {
+        frequency.first().copied()
+    };
+    if let (true, Some(x)) = (margins.gain_margin.is_finite(), phase_crossover) {
+        let gm = 20.0 * margins.gain_margin.log10();
+        gain_markers.push(Marker {
+            x,
+            y: (0.0, -gm),
+            label: format!("GM {} dB", NiceFloat(gm)),
+        });
+    }
+    let mut phase_markers = vec![];
+    if margins.phase_margin.is_finite() {
+        // the plotted phase is unwrapped, so start at the nearest sample
+        let nearest = frequency
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let distance = |w: f64| (w / margins.gain_crossover).ln().abs();
+                distance(**a).total_cmp(&distance(**b))
+            })
+            .map(|(i, _)| i);
+        if let Some(i) = nearest {
+            let p = phase[(0, i)];
+            phase_markers.push(Marker {
+                x: margins.gain_crossover,
+                y: (p - margins.phase_margin, p),
+                label: format!("PM {} deg", NiceFloat(margins.phase_margin)),
+            });
+        }
+    }
+    (gain_markers, phase_markers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::frequency_response::stability_margins;
+    use engine::transfer_function::ContinuousTransferFunction;
+    use ndarray::array;
+
+    #[test]
+    fn gain_margin_marker_at_zero_frequency() {
+        // -2 / (s + 1) crosses -180 degrees at s = 0
+        let tf = ContinuousTransferFunction::new(array![-2.0], array![1.0, 1.0]).unwrap();
+        let margins = stability_margins(
+            |w| tf.frequency_response(array![w].view())[0],
+            &tf.poles(),
+            None,
+        );
+        let frequency = Array1::logspace(10.0, -2.0, 2.0, 41);
+        let phase = Array2::from_elem((1, frequency.len()), 180.0);
+        let (gain_markers, _) = margin_markers(Some(margins), &frequency, &phase);
+        assert_eq!(gain_markers.len(), 1);
+        assert_eq!(gain_markers[0].x, frequency[0]);
+        assert_eq!(gain_markers[0].label, "GM -6.021 dB");
+    }
+}
@@ -4,15 +4,49 @@ use std::rc::Rc;
 use engine::NiceFloat;
 use leptos::*;
 use leptos_use::{use_element_size, UseElementSizeReturn};
-use ndarray::{Array2, ArrayView1};
+use ndarray::{Array1, Array2, ArrayView1};
 
+/// Vertical line from `y.0` to `y.1` at `x` with a label next to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    pub x: f64,
+    pub y: (f64, f64),
+    pub label: String,
+}
+
+/// Point marked with a cross and a label
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub label: String,
+}
+
+/// Plots every row of `data` over `x`. Without `x` the x axis shows the column index.
+/// With `xy` pairs of rows are the x and y coordinates of a curve.
 #[component]
-pub fn SVGPlot(#[prop(into)] data: Signal<Rc<Array2<f64>>>, initial_height: f64) -> impl IntoView {
+pub fn SVGPlot(
+    #[prop(into)] data: Signal<Rc<Array2<f64>>>,
+    #[prop(optional)] x: Option<Rc<Array1<f64>>>,
+    #[prop(optional)] xy: bool,
+    /// arrows along the curves in the direction of increasing index
+    #[prop(optional)]
+    arrows: bool,
+    #[prop(optional)] x_label: Option<&'static str>,
+    #[prop(optional)] y_label: Option<&'static str>,
+    /// logarithmic scaling of the x axis, `x` must be positive
+    #[prop(optional)]
+    log_x: bool,
+    #[prop(optional)] markers: Vec<Marker>,
+    #[prop(optional)] points: Vec<Point>,
+    initial_height: f64,
+) -> impl IntoView {
     let el = create_node_ref::<html::Div>();
     let UseElementSizeReturn { width, height } = use_element_size(el);
 
     let colors = &["red", "blue"];
-    let margin_left = 50.;
+    // leave space for the y label
+    let margin_left = if y_label.is_some() { 70. } else { 50. };
     let margin_top = 20.;
     let margin_right = 20.;
     let margin_bottom = 50.;
@@ -21,17 +55,74 @@ pub fn SVGPlot(#[prop(into)] data: Signal<Rc<Array2<f64>>>, initial_height: f64)
     let height = move || height.get().max(margin_top + margin_bottom + 5.0);
     let graph_width = move || width.get() - margin_left - margin_right;
     let graph_height = move || height() - margin_top - margin_bottom;
-    let x_min_max = create_memo(move |_| (0.0, data.get().ncols() as f64 - 1.0));
+    // with log scaling the plot works on the exponents
+    let x_values = create_memo(move |_| match &x {
+        Some(x) if log_x => Rc::new(x.mapv(f64::log10)),
+        Some(x) => x.clone(),
+        None => Rc::new(Array1::range(0.0, data.get().ncols() as f64, 1.0)),
+    });
+    // x and y coordinates of every curve
+    let curves = create_memo(move |_| {
+        let data = data.get();
+        let curves: Vec<_> = if xy {
+            data.axis_chunks_iter(ndarray::Axis(0), 2)
+                .filter(|pair| pair.nrows() == 2)
+                .map(|pair| (pair.row(0).to_owned(), pair.row(1).to_owned()))
+                .collect()
+        } else {
+            let x = x_values.get();
+            data.rows()
+                .into_iter()
+                .map(|row| ((*x).clone(), row.to_owned()))
+                .collect()
+        };
+        Rc::new(curves)
+    });
+    let scale_x = move |x: f64| if log_x { x.log10() } else { x };
+    let markers: Vec<_> = markers
+        .into_iter()
+        .map(|m| Marker {
+            x: scale_x(m.x),
+            ..m
+        })
+        .collect();
+    let points: Vec<_> = points
+        .into_iter()
+        .map(|p| Point {
+            x: scale_x(p.x),
+            ..p
+        })
+        .collect();
+    let extra_x: Vec<_> = points.iter().map(|p| p.x).collect();
+    let extra_y: Vec<_> = (markers.iter().flat_map(|m| [m.y.0, m.y.1]))
+        .chain(points.iter().map(|p| p.y))
+        .collect();
+    let min_max = |values: &mut dyn Iterator<Item = &f64>| {
+        values.fold((f64::MAX, f64::MIN), |(min, max), v| {
+            (min.min(*v), max.max(*v))
+        })
+    };
+    let x_min_max = create_memo(move |_| {
+        let curves = curves.get();
+        let (min, max) = min_max(&mut curves.iter().flat_map(|c| &c.0).chain(&extra_x));
+        if min > max {
+            (0.0, 0.0)
+        } else {
+            (min, max)
+        }
+    });
     let y_min_max = create_memo(move |_| {
-        (
-            data.get().fold(f64::MAX, |a, b| a.min(*b)),
-            data.get().fold(f64::MIN, |a, b| a.max(*b)),
-        )
+        let curves = curves.get();
+        min_max(&mut curves.iter().flat_map(|c| &c.1).chain(&extra_y))
     });
 
     let x_axis = create_memo(move |_| {
         let max_num_ticks = (graph_width() / tightest_x_tick_spacing).floor() as usize + 1;
-        Axis::new(x_min_max.get(), max_num_ticks)
+        if log_x {
+            Axis::new_log(x_min_max.get(), max_num_ticks)
+        } else {
+            Axis::new(x_min_max.get(), max_num_ticks)
+        }
     });
     let y_axis = create_memo(move |_| {
         let max_num_ticks = (graph_height() / tightest_y_tick_spacing).floor() as usize + 1;
@@ -48,14 +139,26 @@ pub fn SVGPlot(#[prop(into)] data: Signal<Rc<Array2<f64>>>, initial_height: f64)
                 d={move || format!("M 0,0 V{} H{} V0 H0", -graph_height(), graph_width())} />
             {move || {
                 let mapping = mapping.get();
-                data.get().axis_iter(ndarray::Axis(0)).enumerate().map(
-                    |(i, row)| make_path(colors[i % colors.len()], row, &mapping)
-                ).collect_view()
+                curves.get().iter().enumerate().map(|(i, (x, y))| {
+                    let color = colors[i % colors.len()];
+                    view! {
+                        {make_path(color, x.view(), y.view(), &mapping)}
+                        {arrows.then(|| make_arrows(color, x.view(), y.view(), &mapping))}
+                    }
+                }).collect_view()
+            }}
+            {move || {
+                let mapping = mapping.get();
+                markers.iter().map(|marker| make_marker(marker, &mapping)).collect_view()
+            }}
+            {move || {
+                let mapping = mapping.get();
+                points.iter().map(|point| make_point(point, &mapping)).collect_view()
             }}
             {move || {
                 let mapping = mapping.get();
                 x_axis.get().ticks()
-                    .map(|pos| make_x_tick(pos, &mapping, graph_height()))
+                    .map(|pos| make_x_tick(pos, &mapping, graph_height(), log_x))
                     .collect_view()
             }}
             {move || {
@@ -64,6 +167,13 @@ pub fn SVGPlot(#[prop(into)] data: Signal<Rc<Array2<f64>>>, initial_height: f64)
                     .map(|pos| make_y_tick(pos, &mapping, graph_width()))
                     .collect_view()
             }}
+            {move || x_label.map(|label| view! {
+                <text text-anchor="middle" x=graph_width() / 2.0 y=42>{label}</text>
+            })}
+            {move || y_label.map(|label| view! {
+                <text text-anchor="middle" transform="rotate(-90)"
+                    x=graph_height() / 2.0 y=12.0 - margin_left>{label}</text>
+            })}
             <path fill="none" stroke="black"
                 d={move || format!("M 0,0 V{} H{} V0 H0", -graph_height(), graph_width())} />
         </g>
@@ -74,7 +184,7 @@ pub fn SVGPlot(#[prop(into)] data: Signal<Rc<Array2<f64>>>, initial_height: f64)
 
 /// Conversion to svg space
 #[derive(Clone, Copy, Debug, PartialEq)]
-struct Mapping {
+pub(crate) struct Mapping {
     /// size of one graph space unit in svg space
     x_scale: f64,
     /// size of one graph space unit in svg space
@@ -86,7 +196,7 @@ struct Mapping {
 }
 
 impl Mapping {
-    fn new(x: Axis, y: Axis, width: f64, heigth: f64) -> Self {
+    pub(crate) fn new(x: Axis, y: Axis, width: f64, heigth: f64) -> Self {
         Self {
             x_scale: width / (x.max - x.min),
             y_scale: heigth / (y.max - y.min),
@@ -95,13 +205,13 @@ impl Mapping {
         }
     }
 
-    fn map_x(&self, x: f64) -> f64 {
+    pub(crate) fn map_x(&self, x: f64) -> f64 {
         (x - self.x_min) * self.x_scale
     }
-    fn map_y(&self, y: f64) -> f64 {
+    pub(crate) fn map_y(&self, y: f64) -> f64 {
         (self.y_min - y) * self.y_scale
     }
-    fn map(&self, (x, y): (f64, f64)) -> (f64, f64) {
+    pub(crate) fn map(&self, (x, y): (f64, f64)) -> (f64, f64) {
         (self.map_x(x), self.map_y(y))
     }
 }
@@ -111,11 +221,11 @@ impl Mapping {
 /// TODO:
 /// - When displaying radians use multiples of pi
 /// - rad2deg
-/// - logarithmic scales (for data and or ticks)
+/// - minor ticks for logarithmic scales
 /// - optionally force 0 to be included
 /// - symmetric wrt. 0
 #[derive(Clone, Copy, Debug, PartialEq)]
-struct Axis {
+pub(crate) struct Axis {
     min: f64,
     max: f64,
     /// distance of two tick marks in graph space
@@ -123,7 +233,7 @@ struct Axis {
 }
 
 impl Axis {
-    fn new((mut min, mut max): (f64, f64), max_num_ticks: usize) -> Self {
+    pub(crate) fn new((mut min, mut max): (f64, f64), max_num_ticks: usize) -> Self {
         if max == min {
             min -= 0.5;
             max += 0.5;
@@ -145,7 +255,17 @@ impl Axis {
         Axis { min, max, step }
     }
 
-    fn ticks(&self) -> impl Iterator<Item = f64> {
+    /// Axis over the exponents of a logarithmic scale with ticks at whole decades
+    fn new_log((mut min, mut max): (f64, f64), max_num_ticks: usize) -> Self {
+        if max == min {
+            min -= 0.5;
+            max += 0.5;
+        }
+        let step = ((max - min) / max_num_ticks.max(1) as f64).ceil().max(1.0);
+        Axis { min, max, step }
+    }
+
+    pub(crate) fn ticks(&self) -> impl Iterator<Item = f64> {
         let t_min = (self.min / self.step).ceil() as isize;
         let t_max = (self.max / self.step).floor() as isize;
         let step = self.step;
@@ -153,10 +273,15 @@ impl Axis {
     }
 }
 
-fn make_path(color: &'static str, y: ArrayView1<f64>, m: &Mapping) -> impl IntoView {
+fn make_path(
+    color: &'static str,
+    x: ArrayView1<f64>,
+    y: ArrayView1<f64>,
+    m: &Mapping,
+) -> impl IntoView {
     let mut path = "M".to_string();
-    for (x, y) in y.iter().enumerate() {
-        let (x, y) = m.map((x as f64, *y));
+    for (x, y) in x.iter().zip(y.iter()) {
+        let (x, y) = m.map((*x, *y));
         write!(path, " {},{}", x, y).unwrap();
     }
     view! {
@@ -164,17 +289,84 @@ fn make_path(color: &'static str, y: ArrayView1<f64>, m: &Mapping) -> impl IntoV
     }
 }
 
-fn make_x_tick(pos: f64, m: &Mapping, graph_height: f64) -> impl IntoView {
+/// Arrowheads at evenly spaced positions along the curve
+fn make_arrows(
+    color: &'static str,
+    x: ArrayView1<f64>,
+    y: ArrayView1<f64>,
+    m: &Mapping,
+) -> impl IntoView {
+    const NUM_ARROWS: usize = 4;
+    let points: Vec<_> = x
+        .iter()
+        .zip(y.iter())
+        .map(|(x, y)| m.map((*x, *y)))
+        .collect();
+    let length = |(x0, y0): (f64, f64), (x1, y1): (f64, f64)| (x1 - x0).hypot(y1 - y0);
+    let total: f64 = points.windows(2).map(|p| length(p[0], p[1])).sum();
+    let mut path = String::new();
+    let mut next = 0;
+    let mut travelled = 0.0;
+    for p in points.windows(2) {
+        let segment = length(p[0], p[1]);
+        travelled += segment;
+        let position = (next as f64 + 0.5) / NUM_ARROWS as f64 * total;
+        if next == NUM_ARROWS || travelled < position || segment == 0.0 {
+            continue;
+        }
+        next += 1;
+        // tip at the end of the segment, pointing along it
+        let (dx, dy) = ((p[1].0 - p[0].0) / segment, (p[1].1 - p[0].1) / segment);
+        let (tip_x, tip_y) = p[1];
+        let (base_x, base_y) = (tip_x - 10.0 * dx, tip_y - 10.0 * dy);
+        write!(
+            path,
+            "M {},{} L {tip_x},{tip_y} L {},{} Z ",
+            base_x - 4.0 * dy,
+            base_y + 4.0 * dx,
+            base_x + 4.0 * dy,
+            base_y - 4.0 * dx,
+        )
+        .unwrap();
+    }
+    view! {
+        <path fill=color stroke="none" d=path/>
+    }
+}
+
+fn make_marker(marker: &Marker, m: &Mapping) -> impl IntoView {
+    let x = m.map_x(marker.x);
+    let (y0, y1) = (m.map_y(marker.y.0), m.map_y(marker.y.1));
+    view! {
+        <path fill="none" stroke="green" stroke-linecap="round" d=format!("M {x},{y0} V{y1}")/>
+        <text x=x + 5.0 y=(y0 + y1) / 2.0 fill="green">{marker.label.clone()}</text>
+    }
+}
+
+fn make_point(point: &Point, m: &Mapping) -> impl IntoView {
+    let (x, y) = m.map((point.x, point.y));
+    view! {
+        <path fill="none" stroke="black" d=format!("M {},{} l 10,10 m 0,-10 l -10,10", x - 5.0, y - 5.0)/>
+        <text x=x + 8.0 y=y - 8.0>{point.label.clone()}</text>
+    }
+}
+
+pub(crate) fn make_x_tick(pos: f64, m: &Mapping, graph_height: f64, log: bool) -> impl IntoView {
     let p = m.map_x(pos);
+    let label = match pos as i32 {
+        decade @ -3..=3 if log => format!("{}", NiceFloat(10f64.powi(decade))),
+        decade if log => format!("1e{decade}"),
+        _ => format!("{}", NiceFloat(pos)),
+    };
     view! {
-        <text text-anchor="middle" x=p y=20 >{format!("{}", NiceFloat(pos))}</text>
+        <text text-anchor="middle" x=p y=20 >{label}</text>
         <path fill="none" stroke="gray" stroke-width=1 d=format!("M {p},0 V{}", -graph_height)/>
         <path fill="none" stroke="black" d=format!("M {p},0 V-5")/>
         <path fill="none" stroke="black" d=format!("M {p},{} v5", -graph_height)/>
     }
 }
 
-fn make_y_tick(pos: f64, m: &Mapping, graph_width: f64) -> impl IntoView {
+pub(crate) fn make_y_tick(pos: f64, m: &Mapping, graph_width: f64) -> impl IntoView {
     let p = m.map_y(pos);
     view! {
         <text text-anchor="end" x=-5 y=p>{format!("{}", NiceFloat(pos))}</text>
@@ -0,0 +1,216 @@
+use std::fmt::Write;
+use std::rc::Rc;
+
+use engine::root_locus::RootLocus;
+use engine::{Complex, NiceComplex, NiceFloat};
+use leptos::*;
+use leptos_use::{use_element_size, UseElementSizeReturn};
+use ndarray::Array1;
+
+use crate::svg_plot::{make_x_tick, make_y_tick, Axis, Mapping};
+
+/// Poles as crosses and zeros as circles in the complex plane with equal
+/// scaling of both axes. Discrete models show the unit circle, continuous
+/// models the imaginary axis.
+///
+/// With a root locus the branches are drawn as well. Clicking shows the
+/// gain of the closest point on the locus.
+#[component]
+pub fn SVGPzMap(
+    poles: Rc<Array1<Complex<f64>>>,
+    zeros: Rc<Array1<Complex<f64>>>,
+    discrete: bool,
+    #[prop(optional)] locus: Option<Rc<RootLocus>>,
+    initial_height: f64,
+) -> impl IntoView {
+    let el = create_node_ref::<html::Div>();
+    let UseElementSizeReturn { width, height } = use_element_size(el);
+
+    let margin_left = 70.;
+    let margin_top = 20.;
+    let margin_right = 20.;
+    let margin_bottom = 50.;
+    let tightest_tick_spacing = 60.;
+    let height = move || height.get().max(margin_top + margin_bottom + 5.0);
+    let graph_width = move || (width.get() - margin_left - margin_right).max(5.0);
+    let graph_height = move || height() - margin_top - margin_bottom;
+
+    // the origin and the unit circle are always visible
+    let (mut x_min, mut x_max, mut y_min, mut y_max): (f64, f64, f64, f64) = if discrete {
+        (-1.0, 1.0, -1.0, 1.0)
+    } else {
+        (0.0, 0.0, 0.0, 0.0)
+    };
+    let branch_points = locus.iter().flat_map(|l| l.branches.iter());
+    for c in poles.iter().chain(zeros.iter()).chain(branch_points) {
+        x_min = x_min.min(c.re);
+        x_max = x_max.max(c.re);
+        y_min = y_min.min(c.im);
+        y_max = y_max.max(c.im);
+    }
+    let extent = (x_max - x_min).max(y_max - y_min);
+    let padding = if extent > 0.0 { 0.1 * extent } else { 1.0 };
+    let (x_min, x_max, y_min, y_max) = (
+        x_min - padding,
+        x_max + padding,
+        y_min - padding,
+        y_max + padding,
+    );
+
+    let mapping = create_memo(move |_| {
+        // widen one of the ranges so that both axes have the same scale
+        let unit = ((x_max - x_min) / graph_width()).max((y_max - y_min) / graph_height());
+        let widen = |min: f64, max: f64, size: f64| {
+            let center = 0.5 * (min + max);
+            (center - 0.5 * size * unit, center + 0.5 * size * unit)
+        };
+        let x_range = widen(x_min, x_max, graph_width());
+        let y_range = widen(y_min, y_max, graph_height());
+        let x_ticks = (graph_width() / tightest_tick_spacing).floor() as usize + 1;
+        let y_ticks = (graph_height() / tightest_tick_spacing).floor() as usize + 1;
+        let (x_axis, y_axis) = (Axis::new(x_range, x_ticks), Axis::new(y_range, y_ticks));
+        (
+            x_axis,
+            y_axis,
+            Mapping::new(x_axis, y_axis, graph_width(), graph_height()),
+        )
+    });
+
+    // index of the gain and the branch of the selected point on the locus
+    let (selected, set_selected) = create_signal(None::<(usize, usize)>);
+    let on_click = {
+        let locus = locus.clone();
+        move |ev: ev::MouseEvent| {
+            let (Some(locus), Some(el)) = (&locus, el.get_untracked()) else {
+                return;
+            };
+            let rect = el.get_bounding_client_rect();
+            let x = ev.client_x() as f64 - rect.left() - margin_left;
+            let y = ev.client_y() as f64 - rect.top() - (height() - margin_bottom);
+            let (_, _, mapping) = mapping.get_untracked();
+            let distance = |c: &Complex<f64>| {
+                let (px, py) = mapping.map((c.re, c.im));
+                (px - x).hypot(py - y)
+            };
+            let closest = locus
+                .branches
+                .indexed_iter()
+                .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+                .map(|(index, _)| index);
+            set_selected.set(closest);
+        }
+    };
+
+    view! {
+        <div node_ref=el style:overflow="hidden" style:resize="vertical" style:height=format!("{initial_height}px")>
+        <svg width="100%" height="100%" style:stroke-width="2px" on:click=on_click>
+        <g transform=move || format!("translate({margin_left} {})", height() - margin_bottom)>
+            <path fill="white" stroke="none"
+                d={move || format!("M 0,0 V{} H{} V0 H0", -graph_height(), graph_width())} />
+            {move || {
+                let (x_axis, y_axis, mapping) = mapping.get();
+                view! {
+                    {x_axis.ticks().map(|pos| make_x_tick(pos, &mapping, graph_height(), false)).collect_view()}
+                    {y_axis.ticks().map(|pos| make_y_tick(pos, &mapping, graph_width())).collect_view()}
+                }
+            }}
+            {move || {
+                let (_, _, mapping) = mapping.get();
+                let (x0, y0) = mapping.map((0.0, 0.0));
+                if discrete {
+                    let r = mapping.map_x(1.0) - x0;
+                    view! {
+                        <circle cx=x0 cy=y0 r=r fill="none" stroke="black" stroke-width=1 stroke-dasharray="4"/>
+                    }.into_view()
+                } else {
+                    view! {
+                        <path fill="none" stroke="black" stroke-width=1 stroke-dasharray="4"
+                            d=format!("M {x0},0 V{}", -graph_height())/>
+                    }.into_view()
+                }
+            }}
+            {
+                let locus = locus.clone();
+                move || {
+                    let (_, _, mapping) = mapping.get();
+                    locus.as_ref().map(|locus| make_branches(locus, &mapping))
+                }
+            }
+            {move || {
+                let (_, _, mapping) = mapping.get();
+                poles.iter().map(|p| make_pole(*p, &mapping)).collect_view()
+            }}
+            {move || {
+                let (_, _, mapping) = mapping.get();
+                zeros.iter().map(|z| make_zero(*z, &mapping)).collect_view()
+            }}
+            {move || {
+                let (_, _, mapping) = mapping.get();
+                let locus = locus.as_ref()?;
+                let (i, j) = selected.get()?;
+                let root = locus.branches[(i, j)];
+                let (x, y) = mapping.map((root.re, root.im));
+                let variable = if discrete { "z" } else { "s" };
+                Some(view! {
+                    <circle cx=x cy=y r=4 fill="black" stroke="none"/>
+                    <text x=10 y=15.0 - graph_height()>
+                        {format!("K = {}, {variable} = {}", NiceFloat(locus.gains[i]), NiceComplex(root))}
+                    </text>
+                })
+            }}
+            <text text-anchor="middle" x=move || graph_width() / 2.0 y=42>"real"</text>
+            <text text-anchor="middle" transform="rotate(-90)"
+                x=move || graph_height() / 2.0 y=12.0 - margin_left>"imaginary"</text>
+            <path fill="none" stroke="black"
+                d={move || format!("M 0,0 V{} H{} V0 H0", -graph_height(), graph_width())} />
+        </g>
+        </svg>
+        </div>
+    }
+}
+
+/// One path per branch
+fn make_branches(locus: &RootLocus, m: &Mapping) -> impl IntoView {
+    // distinct from the colors of poles and zeros
+    let colors = &["green", "darkorange", "purple"];
+    locus
+        .branches
+        .columns()
+        .into_iter()
+        .enumerate()
+        .map(|(j, branch)| {
+            let mut path = "M".to_string();
+            for c in branch {
+                let (x, y) = m.map((c.re, c.im));
+                write!(path, " {x},{y}").unwrap();
+            }
+            view! {
+                <path fill="none" stroke=colors[j % colors.len()] stroke-linejoin="round" d=path/>
+            }
+        })
+        .collect_view()
+}
+
+/// Cross with the value as tooltip
+fn make_pole(pole: Complex<f64>, m: &Mapping) -> impl IntoView {
+    let (x, y) = m.map((pole.re, pole.im));
+    view! {
+        <g>
+            <title>{format!("pole {}", NiceComplex(pole))}</title>
+            // invisible area that makes hovering easier
+            <circle cx=x cy=y r=8 fill="transparent" stroke="none"/>
+            <path fill="none" stroke="red" d=format!("M {},{} l 10,10 m 0,-10 l -10,10", x - 5.0, y - 5.0)/>
+        </g>
+    }
+}
+
+/// Circle with the value as tooltip
+fn make_zero(zero: Complex<f64>, m: &Mapping) -> impl IntoView {
+    let (x, y) = m.map((zero.re, zero.im));
+    view! {
+        <g>
+            <title>{format!("zero {}", NiceComplex(zero))}</title>
+            <circle cx=x cy=y r=5 fill="transparent" stroke="blue"/>
+        </g>
+    }
+}
@@ -0,0 +1,472 @@
+//! Conversion between continuous and discrete time models
+
+use nalgebra::Complex;
+use ndarray::prelude::*;
+use std::f64::consts::PI;
+
+use crate::error::Error;
+use crate::linalg;
+use crate::state_space::{check_sample_time, ContinuousStateSpaceModel, DiscreteStateSpaceModel};
+use crate::transfer_function::{ContinuousTransferFunction, DiscreteTransferFunction};
+
+/// Discretization method
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Method {
+    /// Zero order hold on the inputs
+    ZeroOrderHold,
+    /// Linear interpolation between the input samples (triangle hold)
+    FirstOrderHold,
+    /// Bilinear transformation, optionally with matching frequency
+    /// responses at the prewarp frequency in rad/s
+    Tustin { prewarp: Option<f64> },
+    /// Poles and zeros are mapped by z = e^(s Ts), zeros at infinity to z = -1
+    Matched,
+}
+
+impl ContinuousStateSpaceModel {
+    pub fn discretize(&self, ts: f64, method: Method) -> Result<DiscreteStateSpaceModel, Error> {
+        check_sample_time(ts)?;
+        let n = self.state_size();
+        let m = self.input_size();
+        let discrete = match method {
+            Method::ZeroOrderHold => {
+                // exp([A B; 0 0] Ts) = [Ad Bd; 0 I]
+                let mut augmented = Array2::zeros((n + m, n + m));
+                augmented.slice_mut(s![..n, ..n]).assign(&(&self.a() * ts));
+                augmented.slice_mut(s![..n, n..]).assign(&(&self.b() * ts));
+                let e = linalg::expm(augmented.view());
+                DiscreteStateSpaceModel::new(
+                    e.slice(s![..n, ..n]),
+                    e.slice(s![..n, n..]),
+                    self.c(),
+                    self.d(),
+                )
+            }
+            Method::FirstOrderHold => {
+                let e = first_order_hold_integrals(self.a(), self.b(), ts);
+                let phi = e.slice(s![..n, ..n]);
+                let gamma1 = e.slice(s![..n, n..n + m]);
+                let gamma2 = e.slice(s![..n, n + m..]);
+                // the state is shifted by gamma2 * u to make the model causal
+                let b = &gamma1 + &phi.dot(&gamma2) - gamma2;
+                let d = &self.d() + &self.c().dot(&gamma2);
+                DiscreteStateSpaceModel::new(phi, b, self.c(), d)
+            }
+            Method::Tustin { prewarp } => {
+                let alpha = tustin_factor(ts, prewarp)?;
+                let e = linalg::inverse((Array2::<f64>::eye(n) * alpha - self.a()).view()).ok_or(
+                    Error::InvalidModel(format!("the model has a pole at s = {alpha}").into()),
+                )?;
+                let scale = (2.0 * alpha).sqrt();
+                let a = e.dot(&(Array2::<f64>::eye(n) * alpha + self.a()));
+                let b = e.dot(&self.b()) * scale;
+                let c = self.c().dot(&e) * scale;
+                let d = &self.d() + &self.c().dot(&e).dot(&self.b());
+                DiscreteStateSpaceModel::new(a, b, c, d)
+            }
+            Method::Matched => ContinuousTransferFunction::from_state_space(self)?
+                .discretize(ts, method)?
+                .convert_to_state_space(),
+        };
+        discrete?.with_sample_time(ts)
+    }
+}
+
+impl DiscreteStateSpaceModel {
+    /// Continuous model that gives this model when discretized with the same method
+    pub fn to_continuous(&self, method: Method) -> Result<ContinuousStateSpaceModel, Error> {
+        let ts = self.sample_time().ok_or_else(unspecified_sample_time)?;
+        let n = self.state_size();
+        let m = self.input_size();
+        let no_equivalent = || Error::InvalidModel("the model has no continuous equivalent".into());
+        match method {
+            Method::ZeroOrderHold => {
+                let mut augmented = Array2::<f64>::eye(n + m);
+                augmented.slice_mut(s![..n, ..n]).assign(&self.a());
+                augmented.slice_mut(s![..n, n..]).assign(&self.b());
+                let log = linalg::logm(augmented.view()).ok_or_else(no_equivalent)? / ts;
+                ContinuousStateSpaceModel::new(
+                    log.slice(s![..n, ..n]),
+                    log.slice(s![..n, n..]),
+                    self.c(),
+                    self.d(),
+                )
+            }
+            Method::FirstOrderHold => {
+                let a = linalg::logm(self.a()).ok_or_else(no_equivalent)? / ts;
+                // the integrals for an identity input matrix give B from the discrete B
+                let e = first_order_hold_integrals(a.view(), Array2::<f64>::eye(n).view(), ts);
+                let phi = self.a();
+                let psi1 = e.slice(s![..n, n..2 * n]);
+                let psi2 = e.slice(s![..n, 2 * n..]);
+                let k = &psi1 + &phi.dot(&psi2) - psi2;
+                let b = linalg::inverse(k.view())
+                    .ok_or_else(no_equivalent)?
+                    .dot(&self.b());
+                let d = &self.d() - &self.c().dot(&psi2.dot(&b));
+                ContinuousStateSpaceModel::new(a, b, self.c(), d)
+            }
+            Method::Tustin { prewarp } => {
+                let alpha = tustin_factor(ts, prewarp)?;
+                let e = linalg::inverse((&self.a() + &Array2::<f64>::eye(n)).view())
+                    .ok_or(Error::InvalidModel("the model has a pole at z = -1".into()))?;
+                let scale = (2.0 * alpha).sqrt();
+                let a = Array2::<f64>::eye(n) * alpha - &e * (2.0 * alpha);
+                let b = e.dot(&self.b()) * scale;
+                let c = self.c().dot(&e) * scale;
+                let d = &self.d() - &self.c().dot(&e).dot(&self.b());
+                ContinuousStateSpaceModel::new(a, b, c, d)
+            }
+            Method::Matched => DiscreteTransferFunction::from_state_space(self)?
+                .to_continuous(method)?
+                .convert_to_state_space(),
+        }
+    }
+}
+
+impl ContinuousTransferFunction {
+    pub fn discretize(&self, ts: f64, method: Method) -> Result<DiscreteTransferFunction, Error> {
+        check_sample_time(ts)?;
+        let discrete = match method {
+            Method::ZeroOrderHold | Method::FirstOrderHold => {
+                let ss = self.convert_to_state_space()?.discretize(ts, method)?;
+                DiscreteTransferFunction::from_state_space(&ss)
+            }
+            Method::Tustin { prewarp } => {
+                // s = alpha (1 - z^-1) / (1 + z^-1), multiplied by (1 + z^-1)^n
+                let alpha = tustin_factor(ts, prewarp)?;
+                let substitute = |p| bilinear(p, [alpha, -alpha], [1.0, 1.0]);
+                let (num, den) = normalize(substitute(self.num()), substitute(self.den()));
+                DiscreteTransferFunction::new(num, den)
+            }
+            Method::Matched => {
+                let (gain, zeros, poles) = zeros_poles_gain(self.num(), self.den());
+                let (dc_gain, integrators) = generalized_dc_gain(gain, &zeros, &poles, 0.0);
+                let map = |roots: Vec<Complex<f64>>| -> Vec<Complex<f64>> {
+                    roots.into_iter().map(|r| (r * ts).exp()).collect()
+                };
+                let mut zeros = map(zeros);
+                let poles = map(poles);
+                zeros.resize(poles.len(), Complex::new(-1.0, 0.0));
+                let (unit_dc_gain, _) = generalized_dc_gain(1.0, &zeros, &poles, 1.0);
+                // s^k corresponds to ((z - 1) / Ts)^k close to s = 0
+                let gain = dc_gain * ts.powi(-integrators) / unit_dc_gain;
+                DiscreteTransferFunction::new(
+                    linalg::poly_from_roots(&zeros) * gain,
+                    linalg::poly_from_roots(&poles),
+                )
+            }
+        };
+        discrete?.with_sample_time(ts)
+    }
+}
+
+impl DiscreteTransferFunction {
+    /// Continuous model that gives this model when discretized with the same method
+    pub fn to_continuous(&self, method: Method) -> Result<ContinuousTransferFunction, Error> {
+        let ts = self.sample_time().ok_or_else(unspecified_sample_time)?;
+        match method {
+            Method::ZeroOrderHold | Method::FirstOrderHold => {
+                let ss = self.convert_to_state_space()?.to_continuous(method)?;
+                ContinuousTransferFunction::from_state_space(&ss)
+            }
+            Method::Tustin { prewarp } => {
+                // z^-1 = (1 - s / alpha) / (1 + s / alpha), multiplied by (1 + s / alpha)^n
+                let alpha = tustin_factor(ts, prewarp)?;
+                let substitute = |p| {
+                    let mut p = bilinear(p, [1.0, 1.0 / alpha], [1.0, -1.0 / alpha]);
+                    p.invert_axis(Axis(0));
+                    p
+                };
+                let (num, den) = normalize(substitute(self.num()), substitute(self.den()));
+                ContinuousTransferFunction::new(num, den)
+            }
+            Method::Matched => {
+                let (gain, zeros, poles) = zeros_poles_gain(self.num(), self.den());
+                let (dc_gain, integrators) = generalized_dc_gain(gain, &zeros, &poles, 1.0);
+                let map = |roots: Vec<Complex<f64>>| -> Result<Vec<Complex<f64>>, Error> {
+                    roots
+                        .into_iter()
+                        .map(|r| {
+                            // integrators map to s = 0 exactly
+                            if is_close(r, 1.0, 1.0) {
+                                Ok(Complex::new(0.0, 0.0))
+                            } else if r.re <= 0.0 && r.im.abs() <= ROOT_TOLERANCE {
+                                Err(Error::InvalidModel(
+                                    format!("z = {} has no continuous equivalent", r.re).into(),
+                                ))
+                            } else {
+                                Ok(r.ln() / ts)
+                            }
+                        })
+                        .collect()
+                };
+                // zeros at z = -1 come from zeros at infinity
+                let zeros = map(zeros
+                    .into_iter()
+                    .filter(|z| !is_close(*z, -1.0, 1.0))
+                    .collect())?;
+                let poles = map(poles)?;
+                let (unit_dc_gain, _) = generalized_dc_gain(1.0, &zeros, &poles, 0.0);
+                let gain = dc_gain * ts.powi(integrators) / unit_dc_gain;
+                ContinuousTransferFunction::new(
+                    linalg::poly_from_roots(&zeros) * gain,
+                    linalg::poly_from_roots(&poles),
+                )
+            }
+        }
+    }
+}
+
+fn unspecified_sample_time() -> Error {
+    Error::InvalidModel("the sample time of the model is not specified".into())
+}
+
+/// alpha in s = alpha (z - 1) / (z + 1)
+fn tustin_factor(ts: f64, prewarp: Option<f64>) -> Result<f64, Error> {
+    match prewarp {
+        None => Ok(2.0 / ts),
+        Some(w) if w > 0.0 && w < PI / ts => Ok(w / (w * ts / 2.0).tan()),
+        Some(w) => Err(Error::InvalidModel(
+            format!("prewarp frequency {w} must be between 0 and the Nyquist frequency").into(),
+        )),
+    }
+}
+
+/// exp([A B 0; 0 0 I; 0 0 0] Ts) = [Phi Gamma1 Gamma2; 0 I I; 0 0 I]
+fn first_order_hold_integrals(
+    a: ArrayView2<'_, f64>,
+    b: ArrayView2<'_, f64>,
+    ts: f64,
+) -> Array2<f64> {
+    let n = a.nrows();
+    let m = b.ncols();
+    let mut augmented = Array2::zeros((n + 2 * m, n + 2 * m));
+    augmented.slice_mut(s![..n, ..n]).assign(&(&a * ts));
+    augmented.slice_mut(s![..n, n..n + m]).assign(&(&b * ts));
+    augmented
+        .slice_mut(s![n..n + m, n + m..])
+        .assign(&Array2::<f64>::eye(m));
+    linalg::expm(augmented.view())
+}
+
+/// sum_i p[i] first^(n-i) second^i for the polynomials `first` and `second`
+/// of degree one, lowest power first
+fn bilinear(p: ArrayView1<'_, f64>, first: [f64; 2], second: [f64; 2]) -> Array1<f64> {
+    let n = p.len() - 1;
+    let mut result = Array1::zeros(n + 1);
+    for (i, coefficient) in p.iter().enumerate() {
+        let mut term = vec![*coefficient];
+        for _ in 0..n - i {
+            term = linalg::poly_mul(&term, &first);
+        }
+        for _ in 0..i {
+            term = linalg::poly_mul(&term, &second);
+        }
+        result += &Array1::from_vec(term);
+    }
+    result
+}
+
+/// Scale numerator and denominator such that the leading denominator coefficient is one
+fn normalize(num: Array1<f64>, den: Array1<f64>) -> (Array1<f64>, Array1<f64>) {
+    match den.iter().copied().find(|e| *e != 0.0) {
+        Some(leading) => (num / leading, den / leading),
+        None => (num, den),
+    }
+}
+
+/// Gain, zeros and poles of num / den, both with the highest power first
+fn zeros_poles_gain(
+    num: ArrayView1<'_, f64>,
+    den: ArrayView1<'_, f64>,
+) -> (f64, Vec<Complex<f64>>, Vec<Complex<f64>>) {
+    let leading = |p: ArrayView1<'_, f64>| p.iter().copied().find(|e| *e != 0.0).unwrap_or(0.0);
+    (
+        leading(num) / leading(den),
+        linalg::roots(num),
+        linalg::roots(den),
+    )
+}
+
+/// Distance relative to the magnitude of the roots below which computed
+/// roots are considered equal. Multiple roots are only accurate to about
+/// the square root of the machine precision.
+const ROOT_TOLERANCE: f64 = 1e-6;
+
+/// Whether `root` is `x` up to rounding errors relative to `scale`
+fn is_close(root: Complex<f64>, x: f64, scale: f64) -> bool {
+    (root - x).norm() <= ROOT_TOLERANCE * scale
+}
+
+/// Gain of k * prod(x - zeros) / prod(x - poles) for x close to `origin`,
+/// ignoring the factors with roots at `origin`.
+///
+/// Also returns the number of zeros minus the number of poles at `origin`.
+fn generalized_dc_gain(
+    k: f64,
+    zeros: &[Complex<f64>],
+    poles: &[Complex<f64>],
+    origin: f64,
+) -> (f64, i32) {
+    let scale = zeros
+        .iter()
+        .chain(poles)
+        .map(|r| r.norm())
+        .fold(origin.abs(), f64::max);
+    let at_origin = |r: &Complex<f64>| is_close(*r, origin, scale);
+    let origin = Complex::new(origin, 0.0);
+    let mut gain = Complex::new(k, 0.0);
+    let mut integrators = 0;
+    for z in zeros {
+        if at_origin(z) {
+            integrators += 1;
+        } else {
+            gain *= origin - z;
+        }
+    }
+    for p in poles {
+        if at_origin(p) {
+            integrators -= 1;
+        } else {
+            gain /= origin - p;
+        }
+    }
+    (gain.re, integrators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn first_order() -> ContinuousStateSpaceModel {
+        // 1 / (s + 1)
+        ContinuousStateSpaceModel::new(array![[-1.0]], array![[1.0]], array![[1.0]], array![[0.0]])
+            .unwrap()
+    }
+
+    #[test]
+    fn zero_order_hold() {
+        let d = first_order()
+            .discretize(0.5, Method::ZeroOrderHold)
+            .unwrap();
+        let p = (-0.5f64).exp();
+        assert_relative_eq!(d.a(), array![[p]], epsilon = 1e-12);
+        assert_relative_eq!(d.b(), array![[1.0 - p]], epsilon = 1e-12);
+        let tf = ContinuousTransferFunction::new(array![1.0], array![1.0, 1.0]).unwrap();
+        let d = tf.discretize(0.5, Method::ZeroOrderHold).unwrap();
+        assert_relative_eq!(d.num(), array![0.0, 1.0 - p], epsilon = 1e-12);
+        assert_relative_eq!(d.den(), array![1.0, -p], epsilon = 1e-12);
+    }
+
+    #[test]
+    fn integrator() {
+        // all methods except zero order hold give Ts / 2 (z + 1) / (z - 1)
+        let tf = ContinuousTransferFunction::new(array![1.0], array![1.0, 0.0]).unwrap();
+        for method in [
+            Method::FirstOrderHold,
+            Method::Tustin { prewarp: None },
+            Method::Matched,
+        ] {
+            let d = tf.discretize(0.1, method).unwrap();
+            assert_relative_eq!(d.num(), array![0.05, 0.05], epsilon = 1e-12);
+            assert_relative_eq!(d.den(), array![1.0, -1.0], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let ss = ContinuousStateSpaceModel::new(
+            array![[0.0, 1.0], [-2.0, -0.5]],
+            array![[0.0], [1.0]],
+            array![[1.0, 0.5]],
+            array![[0.1]],
+        )
+        .unwrap();
+        for method in [
+            Method::ZeroOrderHold,
+            Method::FirstOrderHold,
+            Method::Tustin { prewarp: None },
+            Method::Tustin { prewarp: Some(3.0) },
+        ] {
+            let back = ss
+                .discretize(0.2, method)
+                .unwrap()
+                .to_continuous(method)
+                .unwrap();
+            assert_relative_eq!(back.a(), ss.a(), epsilon = 1e-9);
+            assert_relative_eq!(back.b(), ss.b(), epsilon = 1e-9);
+            assert_relative_eq!(back.c(), ss.c(), epsilon = 1e-9);
+            assert_relative_eq!(back.d(), ss.d(), epsilon = 1e-9);
+        }
+        let tf = ContinuousTransferFunction::from_state_space(&ss).unwrap();
+        for method in [Method::Tustin { prewarp: None }, Method::Matched] {
+            let back = tf
+                .discretize(0.2, method)
+                .unwrap()
+                .to_continuous(method)
+                .unwrap();
+            assert_relative_eq!(back.num(), tf.num(), epsilon = 1e-9);
+            assert_relative_eq!(back.den(), tf.den(), epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn matched_round_trip_with_zeros_at_infinity() {
+        // relative degree two gives a double zero at z = -1, whose computed
+        // roots are not exactly -1
+        let tf = ContinuousTransferFunction::new(array![1.0], array![1.0, 3.0, 2.0]).unwrap();
+        let back = tf
+            .discretize(0.1, Method::Matched)
+            .unwrap()
+            .to_continuous(Method::Matched)
+            .unwrap();
+        assert_relative_eq!(back.num(), tf.num(), epsilon = 1e-9);
+        assert_relative_eq!(back.den(), tf.den(), epsilon = 1e-9);
+        // a double integrator
+        let tf = ContinuousTransferFunction::new(array![1.0, 1.0], array![1.0, 0.0, 0.0]).unwrap();
+        let back = tf
+            .discretize(0.1, Method::Matched)
+            .unwrap()
+            .to_continuous(Method::Matched)
+            .unwrap();
+        assert_relative_eq!(back.num(), tf.num(), epsilon = 1e-6);
+        assert_relative_eq!(back.den(), tf.den(), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(matches!(
+            first_order().discretize(0.0, Method::ZeroOrderHold),
+            Err(Error::InvalidModel(_))
+        ));
+        assert!(matches!(
+            first_order().discretize(
+                0.1,
+                Method::Tustin {
+                    prewarp: Some(100.0)
+                }
+            ),
+            Err(Error::InvalidModel(_))
+        ));
+        let d = DiscreteStateSpaceModel::new(
+            array![[-0.5]],
+            array![[1.0]],
+            array![[1.0]],
+            array![[0.0]],
+        )
+        .unwrap();
+        assert!(matches!(
+            d.to_continuous(Method::ZeroOrderHold),
+            Err(Error::InvalidModel(_))
+        ));
+        // a pole on the negative real axis has no continuous equivalent
+        assert!(matches!(
+            d.with_sample_time(0.1)
+                .unwrap()
+                .to_continuous(Method::ZeroOrderHold),
+            Err(Error::InvalidModel(_))
+        ));
+    }
+}
@@ -4,15 +4,23 @@ use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
 
-use crate::state_space::DiscreteStateSpaceModel;
-use crate::transfer_function::DiscreteTransferFunction;
+use crate::error::Error;
+use crate::ode::{Integrator, Ode, Solver};
+use crate::state_space::{ContinuousStateSpaceModel, DiscreteStateSpaceModel};
+use crate::transfer_function::{ContinuousTransferFunction, DiscreteTransferFunction};
+use crate::NiceFloat;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum SystemBlock {
     StateSpace(Rc<DiscreteStateSpaceModel>),
     TransferFunction(Rc<DiscreteTransferFunction>),
-    Difference,
-    // SubSystem(Rc<CompoundDiscreteSystem>),
+    ContinuousStateSpace(Rc<ContinuousStateSpaceModel>),
+    ContinuousTransferFunction(Rc<ContinuousTransferFunction>),
+    /// Sum of the input signals, weighted with the given gains
+    Sum(Rc<[f64]>),
+    /// Static gain matrix. A 1x1 gain is applied to every element of the input.
+    Gain(Rc<Array2<f64>>),
+    SubSystem(Rc<CompoundSystem>),
 }
 
 impl fmt::Display for SystemBlock {
@@ -20,7 +28,43 @@ impl fmt::Display for SystemBlock {
         match self {
             SystemBlock::StateSpace(ss) => ss.fmt(f),
             SystemBlock::TransferFunction(tf) => tf.fmt(f),
-            SystemBlock::Difference => f.write_str("−"),
+            SystemBlock::ContinuousStateSpace(ss) => ss.fmt(f),
+            SystemBlock::ContinuousTransferFunction(tf) => tf.fmt(f),
+            SystemBlock::Sum(gains) => {
+                f.write_str("Σ")?;
+                for gain in gains.iter() {
+                    let sign = if *gain < 0.0 { "−" } else { "+" };
+                    if gain.abs() == 1.0 {
+                        write!(f, " {sign}")?;
+                    } else {
+                        write!(f, " {sign}{}", NiceFloat(gain.abs()))?;
+                    }
+                }
+                Ok(())
+            }
+            SystemBlock::Gain(k) if k.len() == 1 => write!(f, "× {}", NiceFloat(k[(0, 0)])),
+            SystemBlock::Gain(k) => write!(f, "× {k}"),
+            SystemBlock::SubSystem(sys) => {
+                let names: Vec<_> = sys.components.iter().map(|c| &*c.name).collect();
+                write!(f, "{{ {} }}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl SystemBlock {
+    /// Time between two steps in seconds, `None` for static blocks and
+    /// blocks that run at the rate of the surrounding system.
+    /// For subsystems this is their base rate.
+    pub fn sample_time(&self) -> Option<f64> {
+        match self {
+            SystemBlock::StateSpace(ss) => ss.sample_time(),
+            SystemBlock::TransferFunction(tf) => tf.sample_time(),
+            SystemBlock::ContinuousStateSpace(_)
+            | SystemBlock::ContinuousTransferFunction(_)
+            | SystemBlock::Sum(_)
+            | SystemBlock::Gain(_) => None,
+            SystemBlock::SubSystem(sys) => sys.sample_time(),
         }
     }
 }
@@ -29,18 +73,172 @@ impl fmt::Display for SystemBlock {
 pub struct Simulation {
     blocks: Vec<SimulationBlock>,
     execution_plan: Vec<ExecutionStep>,
+    input_names: Rc<[Rc<str>]>,
     input_signal_mapping: Slice,
-    output_signal_mapping: usize,
+    output_signal_mapping: Vec<Slice>,
+    output_size: usize,
     state_size: usize,
     signals_size: usize,
+    sample_time: Option<f64>,
+}
+
+/// Model that is executed by a simulation block
+#[derive(Clone, Debug)]
+enum BlockModel {
+    Discrete(Rc<DiscreteStateSpaceModel>),
+    Continuous(Rc<ContinuousStateSpaceModel>),
+}
+
+impl BlockModel {
+    fn input_size(&self) -> usize {
+        match self {
+            BlockModel::Discrete(m) => m.input_size(),
+            BlockModel::Continuous(m) => m.input_size(),
+        }
+    }
+
+    fn output_size(&self) -> usize {
+        match self {
+            BlockModel::Discrete(m) => m.output_size(),
+            BlockModel::Continuous(m) => m.output_size(),
+        }
+    }
+
+    fn state_size(&self) -> usize {
+        match self {
+            BlockModel::Discrete(m) => m.state_size(),
+            BlockModel::Continuous(m) => m.state_size(),
+        }
+    }
+
+    fn has_feedthrough(&self) -> bool {
+        match self {
+            BlockModel::Discrete(m) => m.has_feedthrough(),
+            BlockModel::Continuous(m) => m.has_feedthrough(),
+        }
+    }
+
+    fn sample_time(&self) -> Option<f64> {
+        match self {
+            BlockModel::Discrete(m) => m.sample_time(),
+            BlockModel::Continuous(_) => None,
+        }
+    }
+
+    fn a(&self) -> ArrayView2<'_, f64> {
+        match self {
+            BlockModel::Discrete(m) => m.a(),
+            BlockModel::Continuous(m) => m.a(),
+        }
+    }
+
+    fn b(&self) -> ArrayView2<'_, f64> {
+        match self {
+            BlockModel::Discrete(m) => m.b(),
+            BlockModel::Continuous(m) => m.b(),
+        }
+    }
+
+    fn c(&self) -> ArrayView2<'_, f64> {
+        match self {
+            BlockModel::Discrete(m) => m.c(),
+            BlockModel::Continuous(m) => m.c(),
+        }
+    }
+
+    fn d(&self) -> ArrayView2<'_, f64> {
+        match self {
+            BlockModel::Discrete(m) => m.d(),
+            BlockModel::Continuous(m) => m.d(),
+        }
+    }
+
+    fn calculate_output(&self, state: ArrayView1<'_, f64>, output: ArrayViewMut1<'_, f64>) {
+        match self {
+            BlockModel::Discrete(m) => m.calculate_output(state, output),
+            BlockModel::Continuous(m) => m.calculate_output(state, output),
+        }
+    }
+
+    fn calculate_output_with_feedthrough(
+        &self,
+        input: ArrayView1<'_, f64>,
+        state: ArrayView1<'_, f64>,
+        output: ArrayViewMut1<'_, f64>,
+    ) {
+        match self {
+            BlockModel::Discrete(m) => m.calculate_output_with_feedthrough(input, state, output),
+            BlockModel::Continuous(m) => m.calculate_output_with_feedthrough(input, state, output),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 struct SimulationBlock {
-    executable: Rc<DiscreteStateSpaceModel>,
-    input_signal_mapping: Slice,
+    name: Rc<str>,
+    executable: BlockModel,
+    /// refers to other simulation blocks
+    reads_input_from: Vec<Signal>,
+    /// the input of the executable is the concatenation of these signals
+    input_signal_mapping: Vec<Slice>,
     state_mapping: Slice,
     output_signal_mapping: Slice,
+    /// the block runs every `period` steps of the base rate,
+    /// its outputs are held in between
+    period: usize,
+}
+
+impl SimulationBlock {
+    fn is_hit(&self, step: usize) -> bool {
+        step.is_multiple_of(self.period)
+    }
+
+    /// Continuous and static blocks, whose outputs are not held between steps
+    fn runs_continuously(&self) -> bool {
+        match &self.executable {
+            BlockModel::Continuous(_) => true,
+            BlockModel::Discrete(m) => m.state_size() == 0 && m.sample_time().is_none(),
+        }
+    }
+
+    fn gather_input(&self, signals: ArrayView1<'_, f64>) -> Array1<f64> {
+        let mut input = Array1::zeros(self.executable.input_size());
+        gather(signals, &self.input_signal_mapping, input.view_mut());
+        input
+    }
+
+    /// Like [`SimulationBlock::gather_input`], but gathers rows of a matrix
+    fn gather_input_rows(&self, signals: ArrayView2<'_, f64>) -> Array2<f64> {
+        let mut input = Array2::zeros((self.executable.input_size(), signals.ncols()));
+        let mut pos = 0;
+        for slice in &self.input_signal_mapping {
+            let len = slice_len(*slice);
+            input
+                .slice_mut(s![pos..pos + len, ..])
+                .assign(&signals.slice(s![*slice, ..]));
+            pos += len;
+        }
+        input
+    }
+}
+
+/// Continuous dynamics of a simulation while the outputs of discrete
+/// blocks are held
+struct ContinuousDynamics<'a> {
+    simulation: &'a Simulation,
+    signals: &'a mut Array1<f64>,
+    /// all blocks are linear, so the Jacobian is constant
+    jacobian: &'a Array2<f64>,
+}
+
+impl Ode for ContinuousDynamics<'_> {
+    fn derivative(&mut self, x: ArrayView1<'_, f64>) -> Array1<f64> {
+        self.simulation.derivative(x, self.signals)
+    }
+
+    fn jacobian(&mut self, _x: ArrayView1<'_, f64>) -> Array2<f64> {
+        self.jacobian.clone()
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -51,174 +249,899 @@ enum ExecutionStep {
 }
 
 impl Simulation {
-    pub fn new(system: &CompoundSystem) -> Option<Self> {
+    pub fn new(system: &CompoundSystem) -> Result<Self, Error> {
         let mut signals_size = 0;
         let mut blocks = vec![];
 
         let mut state_size = 0;
-        let mut dependencies: Vec<Vec<Signal>> = vec![];
-        dependencies.resize(system.components.len() + 1, vec![]);
-        let input_signal_mapping = (signals_size..signals_size + 1).into();
-        signals_size += 1;
+        // every external input is a scalar signal
+        let input_signal_mapping = (signals_size..signals_size + system.inputs.len()).into();
+        signals_size += system.inputs.len();
+
+        // subsystems are flattened, so that only simple blocks remain
+        let external_inputs: Vec<_> = (0..system.inputs.len()).map(Signal::SystemInput).collect();
+        let mut flat_blocks = vec![];
+        let outputs = flatten(system, "", &external_inputs, &mut flat_blocks)?;
+        resolve_sizes(&mut flat_blocks)?;
 
         // build execution graph
         // for now: calculate all signals first, then update discrete states.
         // Can be optimized later to use less intermediate memory.
 
-        for (i, component) in system.components.iter().enumerate() {
-            let executable = match &component.block {
-                SystemBlock::StateSpace(ss) => ss.clone(),
-                SystemBlock::TransferFunction(tf) => {
-                    let b = tf.convert_to_state_space()?;
-                    Rc::new(b)
-                }
-                SystemBlock::Difference => Rc::new(DiscreteStateSpaceModel::new(
-                    Array2::zeros((0, 0)),
-                    Array2::zeros((0, 2)),
-                    Array2::zeros((1, 0)),
-                    array![[1.0, -1.0]],
-                )),
+        // every block runs at an integer multiple of the base rate
+        let base_rate = system.sample_time();
+        for flat_block in flat_blocks {
+            let FlatExecutable::Ready(executable) = flat_block.executable else {
+                unreachable!("all sizes are resolved");
             };
             let state_mapping = (state_size..(state_size + executable.state_size())).into();
             let output_signal_mapping =
                 (signals_size..(signals_size + executable.output_size())).into();
             state_size += executable.state_size();
             signals_size += executable.output_size();
+            let period = match (executable.sample_time(), base_rate) {
+                (Some(ts), Some(base)) => (ts / base).round() as usize,
+                _ => 1,
+            };
 
-            if executable.has_feedthrough() {
-                for input in component.reads_input_from.iter() {
-                    dependencies[i].push(*input);
-                }
-            }
             blocks.push(SimulationBlock {
+                name: flat_block.name,
                 executable,
-                input_signal_mapping: (0..0).into(), // mapped later
+                reads_input_from: flat_block.reads_input_from,
+                input_signal_mapping: vec![], // mapped later
                 state_mapping,
                 output_signal_mapping,
+                period,
             });
         }
 
+        let signal_mapping = |signal: Signal, blocks: &[SimulationBlock]| match signal {
+            Signal::SystemInput(i) => (i..i + 1).into(),
+            Signal::ComponentOutput(i) => blocks[i].output_signal_mapping,
+        };
+
         // adjust reads_input_from after all output signal have been mapped
-        for (i, component) in system.components.iter().enumerate() {
-            let input_mapping = match component.reads_input_from[..] {
-                [input] => match input {
-                    Signal::SystemInput => input_signal_mapping,
-                    Signal::ComponentOutput(i) => blocks[i].output_signal_mapping,
-                },
-                [input1, input2] => {
-                    // support having two inputs (both of size 1) by mapping
-                    // to a slice with two elements and a large step
-                    let input1 = match input1 {
-                        Signal::SystemInput => input_signal_mapping,
-                        Signal::ComponentOutput(i) => blocks[i].output_signal_mapping,
-                    };
-                    let input2 = match input2 {
-                        Signal::SystemInput => input_signal_mapping,
-                        Signal::ComponentOutput(i) => blocks[i].output_signal_mapping,
-                    };
-                    if Some(input1.start + 1) != input1.end || Some(input2.start + 1) != input2.end
-                    {
-                        panic!("can only subtract two signals of size 1");
-                    }
-                    let start = input1.start.min(input2.start);
-                    let end = input1.start.max(input2.start);
-                    Slice::new(start, Some(end + 1), input2.start - input1.start)
-                }
-                _ => panic!(),
-            };
-            blocks[i].input_signal_mapping = input_mapping;
-            // TODO: ensure input and output do not overlap, if we have feedthrough (algebraic loop)
+        for i in 0..blocks.len() {
+            let input_mapping: Vec<Slice> = blocks[i]
+                .reads_input_from
+                .iter()
+                .map(|input| signal_mapping(*input, &blocks))
+                .collect();
+            let input_size: usize = input_mapping.iter().map(|m| slice_len(*m)).sum();
+            let block = &mut blocks[i];
+            if input_size != block.executable.input_size() {
+                return Err(Error::DimensionMismatch(
+                    format!(
+                        "{} expects an input of size {}, but got {}",
+                        block.name,
+                        block.executable.input_size(),
+                        input_size
+                    )
+                    .into(),
+                ));
+            }
+            block.input_signal_mapping = input_mapping;
         }
 
-        // TODO: topological sort
-        // let work_set: Vec<usize> = dependencies
-        //     .iter()
-        //     .enumerate()
-        //     .filter(|(_, v)| v.is_empty())
-        //     .map(|(i, _)| i)
-        //     .collect();
-        // while let Some(next) = work_set.pop() {
-        //     execution_plan.push(next);
-        // }
-
+        // Outputs of blocks without feedthrough only depend on their state,
+        // so they can be calculated first. Blocks with feedthrough have to
+        // wait for all signals they read.
         let mut execution_plan = vec![];
         for (i, block) in blocks.iter().enumerate() {
-            if block.executable.has_feedthrough() {
-                execution_plan.push(ExecutionStep::CalculateOutputWithFeedthrough { system_id: i });
-            } else if block.executable.output_size() > 0 {
+            if !block.executable.has_feedthrough() && block.executable.output_size() > 0 {
                 execution_plan.push(ExecutionStep::CalculateOutput { system_id: i });
             }
         }
+        let feedthrough_order = sort_feedthrough_blocks(&blocks)?;
+        for i in feedthrough_order {
+            execution_plan.push(ExecutionStep::CalculateOutputWithFeedthrough { system_id: i });
+        }
+        // continuous states are integrated separately
         for (i, block) in blocks.iter().enumerate() {
-            if block.executable.state_size() > 0 {
-                execution_plan.push(ExecutionStep::UpdateState { system_id: i });
+            if let BlockModel::Discrete(model) = &block.executable {
+                if model.state_size() > 0 {
+                    execution_plan.push(ExecutionStep::UpdateState { system_id: i });
+                }
             }
         }
 
-        // TODO: take output to be last signal
-        let output_signal_mapping = signals_size - 1;
+        let output_signal_mapping: Vec<Slice> = outputs
+            .iter()
+            .map(|output| signal_mapping(*output, &blocks))
+            .collect();
+        let output_size = output_signal_mapping
+            .iter()
+            .map(|mapping| slice_len(*mapping))
+            .sum();
 
-        Some(Self {
+        Ok(Self {
             blocks,
             state_size,
+            input_names: system.inputs.clone(),
             input_signal_mapping,
             output_signal_mapping,
+            output_size,
             signals_size,
             execution_plan,
+            sample_time: system.sample_time(),
+        })
+    }
+
+    pub fn sample_time(&self) -> Option<f64> {
+        self.sample_time
+    }
+
+    /// Time of each of the first `steps` steps in seconds.
+    /// Without a sample time, steps are one second apart.
+    pub fn time(&self, steps: usize) -> Array1<f64> {
+        let ts = self.sample_time.unwrap_or(1.0);
+        Array1::from_shape_fn(steps, |k| k as f64 * ts)
+    }
+
+    pub fn input_size(&self) -> usize {
+        slice_len(self.input_signal_mapping)
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.output_size
+    }
+
+    /// Whether the system has to be simulated with
+    /// [`Simulation::execute_continuous`]
+    pub fn has_continuous_states(&self) -> bool {
+        self.blocks.iter().any(|block| {
+            matches!(block.executable, BlockModel::Continuous(_))
+                && block.executable.state_size() > 0
         })
     }
 
-    pub fn execute(&self) -> Array1<f64> {
+    /// Simulate the system for `steps` time steps of the base rate.
+    ///
+    /// `input` has one row per time step and one column per system input.
+    /// If it has fewer rows than `steps`, its last row is held.
+    /// The result has one row per time step and one column per system output.
+    pub fn execute(&self, input: ArrayView2<'_, f64>, steps: usize) -> Result<Array2<f64>, Error> {
+        let mut output = Array2::zeros((steps, self.output_size));
+        self.run(input, steps, |i, signals, _| {
+            gather(signals, &self.output_signal_mapping, output.row_mut(i));
+        })?;
+        Ok(output)
+    }
+
+    /// Like [`Simulation::execute`], but records all signals and states.
+    pub fn execute_recording(
+        &self,
+        input: ArrayView2<'_, f64>,
+        steps: usize,
+    ) -> Result<SimulationRecord, Error> {
+        let mut signals = Array2::zeros((steps, self.signals_size));
+        let mut states = Array2::zeros((steps, self.state_size));
+        self.run(input, steps, |i, signals_k, states_k| {
+            signals.row_mut(i).assign(&signals_k);
+            states.row_mut(i).assign(&states_k);
+        })?;
+        Ok(self.record(self.time(steps), signals, states))
+    }
+
+    /// Simulate a system with continuous states for `steps` time steps of
+    /// the base rate, or `steps` seconds if the system has no sample time.
+    ///
+    /// Continuous states are integrated with `solver`. Discrete blocks run
+    /// at their sample times and hold their outputs in between. Row k of
+    /// `input` is held from step k to step k + 1.
+    /// Returns the time and the outputs after every solver step. Times at
+    /// which discrete blocks run appear twice, before and after the blocks
+    /// are executed.
+    pub fn execute_continuous(
+        &self,
+        input: ArrayView2<'_, f64>,
+        steps: usize,
+        solver: Solver,
+    ) -> Result<(Array1<f64>, Array2<f64>), Error> {
+        let mut time = vec![];
+        let mut output = Array2::zeros((0, self.output_size));
+        let mut row = Array1::zeros(self.output_size);
+        self.run_continuous(input, steps, solver, |t, signals, _| {
+            time.push(t);
+            gather(signals, &self.output_signal_mapping, row.view_mut());
+            output.push_row(row.view()).unwrap();
+        })?;
+        Ok((Array1::from_vec(time), output))
+    }
+
+    /// Like [`Simulation::execute_continuous`], but records all signals and states.
+    pub fn execute_continuous_recording(
+        &self,
+        input: ArrayView2<'_, f64>,
+        steps: usize,
+        solver: Solver,
+    ) -> Result<SimulationRecord, Error> {
+        let mut time = vec![];
+        let mut signals = Array2::zeros((0, self.signals_size));
+        let mut states = Array2::zeros((0, self.state_size));
+        self.run_continuous(input, steps, solver, |t, signals_t, states_t| {
+            time.push(t);
+            signals.push_row(signals_t).unwrap();
+            states.push_row(states_t).unwrap();
+        })?;
+        Ok(self.record(Array1::from_vec(time), signals, states))
+    }
+
+    fn record(
+        &self,
+        time: Array1<f64>,
+        signals: Array2<f64>,
+        states: Array2<f64>,
+    ) -> SimulationRecord {
+        let mut signal_mapping = HashMap::new();
+        let mut state_mapping = HashMap::new();
+        for (i, name) in self.input_names.iter().enumerate() {
+            signal_mapping.insert(name.clone(), (i..i + 1).into());
+        }
+        for block in &self.blocks {
+            signal_mapping.insert(block.name.clone(), block.output_signal_mapping);
+            state_mapping.insert(block.name.clone(), block.state_mapping);
+        }
+        SimulationRecord {
+            time,
+            signals,
+            states,
+            signal_mapping,
+            state_mapping,
+        }
+    }
+
+    /// Run the simulation and call `observe` in every step with the step
+    /// index, the signals and the states before they are updated.
+    fn run(
+        &self,
+        input: ArrayView2<'_, f64>,
+        steps: usize,
+        mut observe: impl FnMut(usize, ArrayView1<'_, f64>, ArrayView1<'_, f64>),
+    ) -> Result<(), Error> {
         info!("{self:?}");
+        self.check_input(input, steps)?;
+        if let Some(block) = self.blocks.iter().find(|block| {
+            matches!(block.executable, BlockModel::Continuous(_))
+                && block.executable.state_size() > 0
+        }) {
+            return Err(Error::InvalidModel(
+                format!(
+                    "{} has continuous states and needs an ODE solver",
+                    block.name
+                )
+                .into(),
+            ));
+        }
         let mut states = Array1::zeros(self.state_size);
-        let steps = 35;
-        let mut output = Array1::zeros(steps + 1);
+        let mut signals = Array1::zeros(self.signals_size);
+        for i in 0..steps {
+            let u = input.row(i.min(input.nrows() - 1));
+            signals.slice_mut(s![self.input_signal_mapping]).assign(&u);
+            // blocks that are not hit keep their outputs and states
+            self.calculate_outputs(|block| block.is_hit(i), &mut signals, states.view());
+            observe(i, signals.view(), states.view());
+            self.update_states(|block| block.is_hit(i), signals.view(), &mut states);
+        }
+        Ok(())
+    }
 
-        let u = Array1::from_elem((1,), 1.0);
+    /// Like [`Simulation::run`], but continuous states are integrated
+    /// between the steps and `observe` gets the time.
+    fn run_continuous(
+        &self,
+        input: ArrayView2<'_, f64>,
+        steps: usize,
+        solver: Solver,
+        mut observe: impl FnMut(f64, ArrayView1<'_, f64>, ArrayView1<'_, f64>),
+    ) -> Result<(), Error> {
+        info!("{self:?}");
+        self.check_input(input, steps)?;
+        let interval = self.sample_time.unwrap_or(1.0);
+        let mut integrator = Integrator::new(solver)?;
+        let jacobian = self.jacobian();
+        let mut states = Array1::zeros(self.state_size);
         let mut signals = Array1::zeros(self.signals_size);
-        for i in 0..=steps {
+        for i in 0..steps {
+            let u = input.row(i.min(input.nrows() - 1));
             signals.slice_mut(s![self.input_signal_mapping]).assign(&u);
-            for step in &self.execution_plan {
-                match step {
-                    ExecutionStep::CalculateOutput { system_id } => {
-                        let block = &self.blocks[*system_id];
+            let filter = |block: &SimulationBlock| block.is_hit(i) || block.runs_continuously();
+            self.calculate_outputs(filter, &mut signals, states.view());
+            let mut t = i as f64 * interval;
+            observe(t, signals.view(), states.view());
+            self.update_states(|block| block.is_hit(i), signals.view(), &mut states);
+
+            // outputs of discrete blocks are held until their next step
+            let t_next = (i + 1) as f64 * interval;
+            while t < t_next {
+                let mut dynamics = ContinuousDynamics {
+                    simulation: self,
+                    signals: &mut signals,
+                    jacobian: &jacobian,
+                };
+                t = integrator.step(&mut dynamics, t, &mut states, t_next)?;
+                let filter = SimulationBlock::runs_continuously;
+                self.calculate_outputs(filter, &mut signals, states.view());
+                observe(t, signals.view(), states.view());
+            }
+        }
+        Ok(())
+    }
+
+    fn check_input(&self, input: ArrayView2<'_, f64>, steps: usize) -> Result<(), Error> {
+        if input.ncols() != self.input_size() {
+            return Err(Error::DimensionMismatch(
+                format!(
+                    "system has {} inputs, but input signal has {}",
+                    self.input_size(),
+                    input.ncols()
+                )
+                .into(),
+            ));
+        }
+        if input.nrows() == 0 && steps > 0 {
+            return Err(Error::DimensionMismatch("input signal is empty".into()));
+        }
+        Ok(())
+    }
+
+    /// Time derivative of all states. Discrete states do not change.
+    fn derivative(&self, states: ArrayView1<'_, f64>, signals: &mut Array1<f64>) -> Array1<f64> {
+        self.calculate_outputs(SimulationBlock::runs_continuously, signals, states);
+        let mut derivative = Array1::zeros(states.len());
+        for block in &self.blocks {
+            if let BlockModel::Continuous(model) = &block.executable {
+                let input = block.gather_input(signals.view());
+                model.derivative(
+                    input.view(),
+                    states.slice(s![block.state_mapping]),
+                    derivative.slice_mut(s![block.state_mapping]),
+                );
+            }
+        }
+        derivative
+    }
+
+    /// Jacobian of [`Simulation::derivative`] with respect to the states,
+    /// assembled from the matrices of the blocks
+    fn jacobian(&self) -> Array2<f64> {
+        let n = self.state_size;
+        // derivatives of the signals with respect to the states,
+        // held signals do not depend on the current states
+        let mut sensitivity = Array2::zeros((self.signals_size, n));
+        for step in &self.execution_plan {
+            let (ExecutionStep::CalculateOutput { system_id }
+            | ExecutionStep::CalculateOutputWithFeedthrough { system_id }) = *step
+            else {
+                continue;
+            };
+            let block = &self.blocks[system_id];
+            if !block.runs_continuously() {
+                continue;
+            }
+            let mut output = Array2::zeros((block.executable.output_size(), n));
+            output
+                .slice_mut(s![.., block.state_mapping])
+                .assign(&block.executable.c());
+            if block.executable.has_feedthrough() {
+                let input = block.gather_input_rows(sensitivity.view());
+                output += &block.executable.d().dot(&input);
+            }
+            sensitivity
+                .slice_mut(s![block.output_signal_mapping, ..])
+                .assign(&output);
+        }
+        let mut jacobian = Array2::zeros((n, n));
+        for block in &self.blocks {
+            if let BlockModel::Continuous(model) = &block.executable {
+                let input = block.gather_input_rows(sensitivity.view());
+                let mut rows = jacobian.slice_mut(s![block.state_mapping, ..]);
+                rows.assign(&model.b().dot(&input));
+                let mut own_states = rows.slice_mut(s![.., block.state_mapping]);
+                own_states += &model.a();
+            }
+        }
+        jacobian
+    }
+
+    /// The whole system as one discrete state space model from the system
+    /// inputs to the system outputs, with the base rate as sample time.
+    ///
+    /// Feedback loops are closed, so the poles of the model are the closed
+    /// loop poles. All blocks have to be discrete or static and run at the
+    /// base rate.
+    pub fn linearize(&self) -> Result<DiscreteStateSpaceModel, Error> {
+        if let Some(block) = self.blocks.iter().find(|block| {
+            matches!(block.executable, BlockModel::Continuous(_))
+                && block.executable.state_size() > 0
+        }) {
+            return Err(Error::InvalidModel(
+                format!(
+                    "{} has continuous states, which a discrete model cannot contain",
+                    block.name
+                )
+                .into(),
+            ));
+        }
+        if let Some(block) = self.blocks.iter().find(|block| block.period > 1) {
+            return Err(Error::SampleTimeMismatch(
+                format!(
+                    "{} runs slower than the base rate, so the system cannot be linearized",
+                    block.name
+                )
+                .into(),
+            ));
+        }
+        let (a, b, c, d) = self.state_space_matrices();
+        let model = DiscreteStateSpaceModel::new(a, b, c, d)?;
+        match self.sample_time {
+            Some(ts) => model.with_sample_time(ts),
+            None => Ok(model),
+        }
+    }
+
+    /// Like [`Simulation::linearize`], but for systems whose blocks are
+    /// continuous or static
+    pub fn linearize_continuous(&self) -> Result<ContinuousStateSpaceModel, Error> {
+        if let Some(block) = self.blocks.iter().find(|block| !block.runs_continuously()) {
+            return Err(Error::InvalidModel(
+                format!(
+                    "{} is discrete, which a continuous model cannot contain",
+                    block.name
+                )
+                .into(),
+            ));
+        }
+        let (a, b, c, d) = self.state_space_matrices();
+        ContinuousStateSpaceModel::new(a, b, c, d)
+    }
+
+    /// A, B, C and D of the whole system, assembled from the matrices of
+    /// the blocks like [`Simulation::jacobian`]
+    fn state_space_matrices(&self) -> (Array2<f64>, Array2<f64>, Array2<f64>, Array2<f64>) {
+        let n = self.state_size;
+        let m = self.input_size();
+        // derivatives of the signals with respect to the states and inputs
+        let mut sensitivity = Array2::zeros((self.signals_size, n + m));
+        sensitivity
+            .slice_mut(s![self.input_signal_mapping, n..])
+            .assign(&Array2::eye(m));
+        for step in &self.execution_plan {
+            let (ExecutionStep::CalculateOutput { system_id }
+            | ExecutionStep::CalculateOutputWithFeedthrough { system_id }) = *step
+            else {
+                continue;
+            };
+            let block = &self.blocks[system_id];
+            let mut output = Array2::zeros((block.executable.output_size(), n + m));
+            output
+                .slice_mut(s![.., block.state_mapping])
+                .assign(&block.executable.c());
+            if block.executable.has_feedthrough() {
+                let input = block.gather_input_rows(sensitivity.view());
+                output += &block.executable.d().dot(&input);
+            }
+            sensitivity
+                .slice_mut(s![block.output_signal_mapping, ..])
+                .assign(&output);
+        }
+        let mut dynamics = Array2::zeros((n, n + m));
+        for block in &self.blocks {
+            if block.executable.state_size() == 0 {
+                continue;
+            }
+            let input = block.gather_input_rows(sensitivity.view());
+            let mut rows = dynamics.slice_mut(s![block.state_mapping, ..]);
+            rows.assign(&block.executable.b().dot(&input));
+            let mut own_states = rows.slice_mut(s![.., block.state_mapping]);
+            own_states += &block.executable.a();
+        }
+        let mut output = Array2::zeros((self.output_size, n + m));
+        let mut pos = 0;
+        for mapping in &self.output_signal_mapping {
+            let len = slice_len(*mapping);
+            output
+                .slice_mut(s![pos..pos + len, ..])
+                .assign(&sensitivity.slice(s![*mapping, ..]));
+            pos += len;
+        }
+        (
+            dynamics.slice(s![.., ..n]).to_owned(),
+            dynamics.slice(s![.., n..]).to_owned(),
+            output.slice(s![.., ..n]).to_owned(),
+            output.slice(s![.., n..]).to_owned(),
+        )
+    }
+
+    /// Calculate the outputs of the blocks selected by `filter` in the
+    /// order of the execution plan
+    fn calculate_outputs(
+        &self,
+        filter: impl Fn(&SimulationBlock) -> bool,
+        signals: &mut Array1<f64>,
+        states: ArrayView1<'_, f64>,
+    ) {
+        for step in &self.execution_plan {
+            match *step {
+                ExecutionStep::CalculateOutput { system_id } => {
+                    let block = &self.blocks[system_id];
+                    if filter(block) {
                         block.executable.calculate_output(
                             states.slice(s![block.state_mapping]),
                             signals.slice_mut(s![block.output_signal_mapping]),
                         );
                     }
-                    ExecutionStep::CalculateOutputWithFeedthrough { system_id } => {
-                        let block = &self.blocks[*system_id];
-                        let (input, output) = signals.multi_slice_mut((
-                            s![block.input_signal_mapping],
-                            s![block.output_signal_mapping],
-                        ));
+                }
+                ExecutionStep::CalculateOutputWithFeedthrough { system_id } => {
+                    let block = &self.blocks[system_id];
+                    if filter(block) {
+                        let input = block.gather_input(signals.view());
                         block.executable.calculate_output_with_feedthrough(
                             input.view(),
                             states.slice(s![block.state_mapping]),
-                            output,
-                        );
-                    }
-                    ExecutionStep::UpdateState { system_id } => {
-                        let block = &self.blocks[*system_id];
-                        block.executable.update_state(
-                            signals.slice(s![block.input_signal_mapping]),
-                            states.slice_mut(s![block.state_mapping]),
+                            signals.slice_mut(s![block.output_signal_mapping]),
                         );
                     }
+                }
+                ExecutionStep::UpdateState { .. } => {}
+            }
+        }
+    }
+
+    /// Update the discrete states of the blocks selected by `filter`
+    fn update_states(
+        &self,
+        filter: impl Fn(&SimulationBlock) -> bool,
+        signals: ArrayView1<'_, f64>,
+        states: &mut Array1<f64>,
+    ) {
+        for step in &self.execution_plan {
+            if let ExecutionStep::UpdateState { system_id } = *step {
+                let block = &self.blocks[system_id];
+                let BlockModel::Discrete(model) = &block.executable else {
+                    unreachable!("only discrete states are updated");
                 };
+                if filter(block) {
+                    let input = block.gather_input(signals);
+                    model.update_state(input.view(), states.slice_mut(s![block.state_mapping]));
+                }
+            }
+        }
+    }
+}
+
+/// All signals and states recorded during a simulation
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationRecord {
+    /// time of every step in seconds
+    pub time: Array1<f64>,
+    /// one row per time step, one column per signal element
+    pub signals: Array2<f64>,
+    /// one row per time step, one column per state element.
+    /// Row k holds the states at the beginning of step k.
+    pub states: Array2<f64>,
+    signal_mapping: HashMap<Rc<str>, Slice>,
+    state_mapping: HashMap<Rc<str>, Slice>,
+}
+
+impl SimulationRecord {
+    /// The recorded output of the component with the given name
+    /// (or the system input `u`), one row per time step.
+    pub fn signal(&self, name: &str) -> Option<ArrayView2<'_, f64>> {
+        let mapping = self.signal_mapping.get(name)?;
+        Some(self.signals.slice(s![.., *mapping]))
+    }
+
+    /// The recorded states of the component with the given name,
+    /// one row per time step.
+    pub fn state(&self, name: &str) -> Option<ArrayView2<'_, f64>> {
+        let mapping = self.state_mapping.get(name)?;
+        Some(self.states.slice(s![.., *mapping]))
+    }
+}
+
+/// Number of elements of a contiguous slice with known end
+fn slice_len(slice: Slice) -> usize {
+    (slice.end.unwrap_or(slice.start) - slice.start) as usize
+}
+
+/// Concatenate the `mapping` slices of `signals` into `out`
+fn gather(signals: ArrayView1<'_, f64>, mapping: &[Slice], mut out: ArrayViewMut1<'_, f64>) {
+    let mut pos = 0;
+    for slice in mapping {
+        let len = slice_len(*slice);
+        out.slice_mut(s![pos..pos + len])
+            .assign(&signals.slice(s![*slice]));
+        pos += len;
+    }
+}
+
+fn executable(block: &SystemBlock, name: &str) -> Result<BlockModel, Error> {
+    let with_name = |e| match e {
+        Error::InvalidModel(msg) => Error::InvalidModel(format!("{name}: {msg}").into()),
+        e => e,
+    };
+    Ok(match block {
+        SystemBlock::StateSpace(ss) => BlockModel::Discrete(ss.clone()),
+        SystemBlock::TransferFunction(tf) => {
+            BlockModel::Discrete(Rc::new(tf.convert_to_state_space().map_err(with_name)?))
+        }
+        SystemBlock::ContinuousStateSpace(ss) => BlockModel::Continuous(ss.clone()),
+        SystemBlock::ContinuousTransferFunction(tf) => {
+            BlockModel::Continuous(Rc::new(tf.convert_to_state_space().map_err(with_name)?))
+        }
+        SystemBlock::Gain(k) => BlockModel::Discrete(Rc::new(DiscreteStateSpaceModel::new(
+            Array2::zeros((0, 0)),
+            Array2::zeros((0, k.ncols())),
+            Array2::zeros((k.nrows(), 0)),
+            k.view(),
+        )?)),
+        SystemBlock::Sum(_) | SystemBlock::SubSystem(_) => {
+            unreachable!("size depends on the inputs")
+        }
+    })
+}
+
+/// Executable of a block of the flattened system
+enum FlatExecutable {
+    Ready(BlockModel),
+    /// passes on the outputs of a subsystem
+    Connector,
+    Sum(Rc<[f64]>),
+    ScalarGain(f64),
+}
+
+/// A block of the flattened system. Signals refer to other flat blocks.
+struct FlatBlock {
+    name: Rc<str>,
+    executable: FlatExecutable,
+    reads_input_from: Vec<Signal>,
+}
+
+/// Append all blocks of `system` to `blocks`, recursing into subsystems.
+///
+/// `inputs` are the signals the external inputs of `system` are connected
+/// to. Every subsystem is replaced by its blocks plus a connector whose
+/// output is the concatenation of the outputs of the subsystem.
+/// Returns the outputs of `system`.
+fn flatten(
+    system: &CompoundSystem,
+    prefix: &str,
+    inputs: &[Signal],
+    blocks: &mut Vec<FlatBlock>,
+) -> Result<Vec<Signal>, Error> {
+    // reserve a flat block for every component first, so that components
+    // can read from components declared after them
+    let first = blocks.len();
+    for component in &system.components {
+        let name: Rc<str> = format!("{prefix}{}", component.name).into();
+        let executable = match &component.block {
+            SystemBlock::SubSystem(_) => FlatExecutable::Connector,
+            SystemBlock::Sum(gains) => FlatExecutable::Sum(gains.clone()),
+            SystemBlock::Gain(k) if k.len() == 1 => FlatExecutable::ScalarGain(k[(0, 0)]),
+            block => FlatExecutable::Ready(executable(block, &name)?),
+        };
+        blocks.push(FlatBlock {
+            name,
+            executable,
+            reads_input_from: vec![],
+        });
+    }
+    let resolve = |signal: &Signal| match *signal {
+        Signal::SystemInput(i) => inputs[i],
+        Signal::ComponentOutput(i) => Signal::ComponentOutput(first + i),
+    };
+    for (i, component) in system.components.iter().enumerate() {
+        let reads_input_from: Vec<Signal> =
+            component.reads_input_from.iter().map(resolve).collect();
+        if let SystemBlock::SubSystem(sub_system) = &component.block {
+            let name = blocks[first + i].name.clone();
+            if reads_input_from.len() != sub_system.inputs.len() {
+                return Err(Error::InvalidWiring(
+                    format!(
+                        "{name} expects {} input signals, but got {}",
+                        sub_system.inputs.len(),
+                        reads_input_from.len()
+                    )
+                    .into(),
+                ));
+            }
+            let outputs = flatten(sub_system, &format!("{name}."), &reads_input_from, blocks)?;
+            blocks[first + i].reads_input_from = outputs;
+        } else {
+            blocks[first + i].reads_input_from = reads_input_from;
+        }
+    }
+    Ok(system.outputs.iter().map(resolve).collect())
+}
+
+/// Create the executables of all blocks whose size depends on their inputs.
+///
+/// Their inputs might be such blocks themselves, so this is repeated until
+/// all sizes are known.
+fn resolve_sizes(blocks: &mut [FlatBlock]) -> Result<(), Error> {
+    let is_ready = |block: &FlatBlock| matches!(block.executable, FlatExecutable::Ready(_));
+    loop {
+        let mut progress = false;
+        let mut missing = None;
+        for i in 0..blocks.len() {
+            if let FlatExecutable::Ready(_) = blocks[i].executable {
+                continue;
+            }
+            let sizes = blocks[i]
+                .reads_input_from
+                .iter()
+                .map(|signal| match signal {
+                    Signal::SystemInput(_) => Some(1),
+                    Signal::ComponentOutput(j) => match &blocks[*j].executable {
+                        FlatExecutable::Ready(e) => Some(e.output_size()),
+                        _ => None,
+                    },
+                })
+                .collect::<Option<Vec<usize>>>();
+            let Some(sizes) = sizes else {
+                missing.get_or_insert(i);
+                continue;
+            };
+            let executable = match &blocks[i].executable {
+                FlatExecutable::Connector => {
+                    let n = sizes.iter().sum();
+                    DiscreteStateSpaceModel::new(
+                        Array2::zeros((0, 0)),
+                        Array2::zeros((0, n)),
+                        Array2::zeros((n, 0)),
+                        Array2::eye(n),
+                    )?
+                }
+                FlatExecutable::Sum(gains) => sum_executable(&blocks[i].name, gains, &sizes)?,
+                FlatExecutable::ScalarGain(k) => {
+                    let n = sizes.iter().sum();
+                    DiscreteStateSpaceModel::new(
+                        Array2::zeros((0, 0)),
+                        Array2::zeros((0, n)),
+                        Array2::zeros((n, 0)),
+                        Array2::eye(n) * *k,
+                    )?
+                }
+                FlatExecutable::Ready(_) => unreachable!(),
+            };
+            blocks[i].executable = FlatExecutable::Ready(BlockModel::Discrete(Rc::new(executable)));
+            progress = true;
+        }
+        let Some(missing) = missing else {
+            break;
+        };
+        if !progress {
+            // The remaining blocks wait for each other. All of them have
+            // direct feedthrough, so follow their inputs to find the loop.
+            let mut path = vec![missing];
+            loop {
+                let i = *path.last().unwrap();
+                let next = blocks[i]
+                    .reads_input_from
+                    .iter()
+                    .find_map(|signal| match signal {
+                        Signal::ComponentOutput(j) if !is_ready(&blocks[*j]) => Some(*j),
+                        _ => None,
+                    })
+                    .expect("unresolved block reads an unresolved signal");
+                if let Some(start) = path.iter().position(|j| *j == next) {
+                    let names = path[start..]
+                        .iter()
+                        .chain([&next])
+                        .map(|j| blocks[*j].name.clone())
+                        .collect();
+                    return Err(Error::AlgebraicLoop(names));
+                }
+                path.push(next);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Weighted sum of signals which all have the same size
+fn sum_executable(
+    name: &str,
+    gains: &[f64],
+    sizes: &[usize],
+) -> Result<DiscreteStateSpaceModel, Error> {
+    if gains.len() != sizes.len() {
+        return Err(Error::InvalidWiring(
+            format!(
+                "{name} sums {} signals, but reads {}",
+                gains.len(),
+                sizes.len()
+            )
+            .into(),
+        ));
+    }
+    let n = sizes.first().copied().unwrap_or(0);
+    if sizes.iter().any(|size| *size != n) {
+        return Err(Error::DimensionMismatch(
+            format!("signals summed by {name} have different sizes").into(),
+        ));
+    }
+    let mut d = Array2::zeros((n, n * gains.len()));
+    for (i, gain) in gains.iter().enumerate() {
+        d.slice_mut(s![.., i * n..(i + 1) * n])
+            .diag_mut()
+            .fill(*gain);
+    }
+    DiscreteStateSpaceModel::new(
+        Array2::zeros((0, 0)),
+        Array2::zeros((0, n * gains.len())),
+        Array2::zeros((n, 0)),
+        d,
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VisitState {
+    Unvisited,
+    InProgress,
+    Done,
+}
+
+/// Topological sort of all blocks with direct feedthrough.
+///
+/// A feedthrough block has to be executed after every feedthrough block it
+/// reads from. Returns an error naming the involved blocks if they form an
+/// algebraic loop.
+fn sort_feedthrough_blocks(blocks: &[SimulationBlock]) -> Result<Vec<usize>, Error> {
+    fn visit(
+        i: usize,
+        blocks: &[SimulationBlock],
+        state: &mut [VisitState],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), Error> {
+        match state[i] {
+            VisitState::Done => return Ok(()),
+            VisitState::InProgress => {
+                let start = path.iter().position(|j| *j == i).unwrap_or(0);
+                let names = path[start..]
+                    .iter()
+                    .chain([&i])
+                    .map(|j| blocks[*j].name.clone())
+                    .collect();
+                return Err(Error::AlgebraicLoop(names));
             }
-            let output_this_cycle = signals[self.output_signal_mapping];
-            output[i] = output_this_cycle;
+            VisitState::Unvisited => {}
+        }
+        state[i] = VisitState::InProgress;
+        path.push(i);
+        for input in blocks[i].reads_input_from.iter() {
+            if let Signal::ComponentOutput(j) = *input {
+                if blocks[j].executable.has_feedthrough() {
+                    visit(j, blocks, state, path, order)?;
+                }
+            }
+        }
+        path.pop();
+        state[i] = VisitState::Done;
+        order.push(i);
+        Ok(())
+    }
+
+    let mut state = vec![VisitState::Unvisited; blocks.len()];
+    let mut path = vec![];
+    let mut order = vec![];
+    for (i, block) in blocks.iter().enumerate() {
+        if block.executable.has_feedthrough() {
+            visit(i, blocks, &mut state, &mut path, &mut order)?;
         }
-        output
     }
+    Ok(order)
 }
 
 /// A system consisting of multiple subsystems
 #[derive(Clone, Debug, PartialEq)]
 pub struct CompoundSystem {
     pub components: Vec<CompoundSystemComponent>,
+    pub inputs: Rc<[Rc<str>]>,
+    pub outputs: Rc<[Signal]>,
+    sample_time: Option<f64>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -230,7 +1153,8 @@ pub struct CompoundSystemComponent {
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Signal {
-    SystemInput,
+    /// The external input with the given index
+    SystemInput(usize),
     ComponentOutput(usize),
 }
 
@@ -242,17 +1166,56 @@ pub struct CompoundSystemComponentDefinition {
 }
 
 impl CompoundSystem {
-    pub fn new(components: Vec<CompoundSystemComponentDefinition>) -> Result<Self, Rc<str>> {
+    /// Resolve all signal names of the `components`.
+    ///
+    /// `inputs` names the external inputs of the system. If it is empty,
+    /// the system has a single input `u`.
+    /// `outputs` names the signals that are the outputs of the system.
+    /// If it is empty, the output of the last component is used.
+    /// The sample times of the components have to be integer multiples of
+    /// a common base rate, which becomes the sample time of the system.
+    pub fn new(
+        components: Vec<CompoundSystemComponentDefinition>,
+        inputs: &[Rc<str>],
+        outputs: &[Rc<str>],
+    ) -> Result<Self, Error> {
+        let inputs: Rc<[Rc<str>]> = if inputs.is_empty() {
+            ["u".into()].into()
+        } else {
+            inputs.into()
+        };
         // do name resolution
         let mut signal_names = HashMap::new();
-        signal_names.insert("u".into(), Signal::SystemInput);
+        for (i, input) in inputs.iter().enumerate() {
+            if signal_names.contains_key(input) {
+                return Err(Error::DuplicateName(input.clone()));
+            }
+            signal_names.insert(input.clone(), Signal::SystemInput(i));
+        }
         for (i, sub_system) in components.iter().enumerate() {
             if signal_names.contains_key(&sub_system.name) {
-                return Err(format!("duplicate name {}", sub_system.name).into());
+                return Err(Error::DuplicateName(sub_system.name.clone()));
             }
             signal_names.insert(sub_system.name.clone(), Signal::ComponentOutput(i));
         }
 
+        let resolve = |name: &Rc<str>| {
+            signal_names
+                .get(name)
+                .ok_or_else(|| Error::UnknownSignal(name.clone()))
+                .copied()
+        };
+        let outputs = if outputs.is_empty() {
+            match components.len() {
+                0 => [Signal::SystemInput(0)].into(),
+                n => [Signal::ComponentOutput(n - 1)].into(),
+            }
+        } else {
+            outputs.iter().map(resolve).collect::<Result<_, _>>()?
+        };
+
+        let sample_time = base_sample_time(&components)?;
+
         let components = components
             .into_iter()
             .map(|c| {
@@ -262,17 +1225,571 @@ impl CompoundSystem {
                     reads_input_from: c
                         .reads_input_from
                         .iter()
-                        .map(|input| {
-                            signal_names
-                                .get(input)
-                                .ok_or(format!("signal {} does not exist", input))
-                                .copied()
-                        })
-                        .collect::<Result<_, String>>()?,
+                        .map(resolve)
+                        .collect::<Result<_, _>>()?,
                 })
             })
-            .collect::<Result<_, String>>()?;
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self {
+            components,
+            inputs,
+            outputs,
+            sample_time,
+        })
+    }
+
+    pub fn sample_time(&self) -> Option<f64> {
+        self.sample_time
+    }
+
+    /// The whole system as one discrete state space model,
+    /// see [`Simulation::linearize`]
+    pub fn linearize(&self) -> Result<DiscreteStateSpaceModel, Error> {
+        Simulation::new(self)?.linearize()
+    }
+}
+
+/// Largest sample time that all sample times of the components are
+/// integer multiples of
+fn base_sample_time(
+    components: &[CompoundSystemComponentDefinition],
+) -> Result<Option<f64>, Error> {
+    let mut base: Option<(f64, &str)> = None;
+    for component in components {
+        let Some(ts) = component.block.sample_time() else {
+            continue;
+        };
+        let Some((other, name)) = base else {
+            base = Some((ts, &component.name));
+            continue;
+        };
+        // euclidean algorithm, up to rounding errors
+        let tolerance = 1e-9 * ts.max(other);
+        let (mut a, mut b) = (ts.max(other), ts.min(other));
+        while b > tolerance {
+            (a, b) = (b, a % b);
+            if a - b <= tolerance {
+                b = 0.0;
+            }
+        }
+        if ts.max(other) / a > 1e6 {
+            return Err(Error::SampleTimeMismatch(
+                format!(
+                    "{name} runs every {} s and {} every {} s, which have no common base rate",
+                    NiceFloat(other),
+                    component.name,
+                    NiceFloat(ts)
+                )
+                .into(),
+            ));
+        }
+        base = Some((a, name));
+    }
+    Ok(base.map(|(ts, _)| ts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn gain(k: f64) -> SystemBlock {
+        SystemBlock::TransferFunction(Rc::new(
+            DiscreteTransferFunction::new(array![k], array![1.0]).unwrap(),
+        ))
+    }
+
+    fn difference() -> SystemBlock {
+        SystemBlock::Sum([1.0, -1.0].into())
+    }
+
+    fn component(
+        block: SystemBlock,
+        name: &str,
+        inputs: &[&str],
+    ) -> CompoundSystemComponentDefinition {
+        CompoundSystemComponentDefinition {
+            block,
+            name: name.into(),
+            reads_input_from: inputs.iter().map(|i| (*i).into()).collect(),
+        }
+    }
+
+    #[test]
+    fn feedthrough_blocks_are_executed_in_dependency_order() {
+        let system = CompoundSystem::new(
+            vec![
+                component(gain(2.0), "b", &["a"]),
+                component(gain(2.0), "a", &["u"]),
+                component(gain(2.0), "out", &["b"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        let output = sim.execute(Array2::ones((1, 1)).view(), 36).unwrap();
+        assert_relative_eq!(output, Array2::from_elem((36, 1), 8.0));
+    }
+
+    #[test]
+    fn input_sequence_is_applied_and_held() {
+        let system =
+            CompoundSystem::new(vec![component(gain(2.0), "y", &["u"])], &[], &[]).unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        let output = sim.execute(array![[1.0], [2.0], [3.0]].view(), 5).unwrap();
+        assert_relative_eq!(output, array![[2.0], [4.0], [6.0], [6.0], [6.0]]);
+
+        let err = sim.execute(Array2::ones((3, 2)).view(), 5).unwrap_err();
+        assert_eq!(
+            err,
+            Error::DimensionMismatch("system has 1 inputs, but input signal has 2".into())
+        );
+    }
+
+    #[test]
+    fn declared_outputs() {
+        let components = || {
+            vec![
+                component(gain(2.0), "a", &["u"]),
+                component(gain(3.0), "b", &["u"]),
+            ]
+        };
+        let system = CompoundSystem::new(components(), &[], &["b".into(), "u".into(), "a".into()]);
+        let sim = Simulation::new(&system.unwrap()).unwrap();
+        assert_eq!(sim.output_size(), 3);
+        let output = sim.execute(array![[1.0], [2.0]].view(), 2).unwrap();
+        assert_relative_eq!(output, array![[3.0, 1.0, 2.0], [6.0, 2.0, 4.0]]);
+
+        let err = CompoundSystem::new(components(), &[], &["c".into()]).unwrap_err();
+        assert_eq!(err, Error::UnknownSignal("c".into()));
+    }
+
+    #[test]
+    fn named_inputs() {
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["r", "y"]),
+                component(gain(2.0), "y", &["d"]),
+            ],
+            &["r".into(), "d".into()],
+            &["e".into()],
+        )
+        .unwrap();
+        assert_eq!(
+            system.components[0].reads_input_from[..],
+            [Signal::SystemInput(0), Signal::ComponentOutput(1)]
+        );
+        let sim = Simulation::new(&system).unwrap();
+        assert_eq!(sim.input_size(), 2);
+        let output = sim
+            .execute(array![[1.0, 0.0], [1.0, 1.0]].view(), 2)
+            .unwrap();
+        assert_relative_eq!(output, array![[1.0], [-1.0]]);
+        let record = sim.execute_recording(array![[1.0, 0.5]].view(), 1).unwrap();
+        assert_relative_eq!(record.signal("d").unwrap(), array![[0.5]]);
+
+        let err = CompoundSystem::new(vec![], &["r".into(), "r".into()], &[]).unwrap_err();
+        assert_eq!(err, Error::DuplicateName("r".into()));
+    }
+
+    #[test]
+    fn weighted_sum_of_vector_signals() {
+        let two_outputs = SystemBlock::StateSpace(Rc::new(
+            DiscreteStateSpaceModel::new(
+                Array2::zeros((0, 0)),
+                Array2::zeros((0, 1)),
+                Array2::zeros((2, 0)),
+                array![[1.0], [2.0]],
+            )
+            .unwrap(),
+        ));
+        let system = CompoundSystem::new(
+            vec![
+                component(two_outputs.clone(), "a", &["u"]),
+                component(SystemBlock::Sum([2.0, -0.5].into()), "v", &["a", "a"]),
+                component(
+                    SystemBlock::Sum([1.0, -1.0, 1.0].into()),
+                    "w",
+                    &["u", "u", "u"],
+                ),
+            ],
+            &[],
+            &["v".into(), "w".into()],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        let output = sim.execute(array![[2.0]].view(), 1).unwrap();
+        assert_relative_eq!(output, array![[3.0, 6.0, 2.0]]);
+
+        let system = CompoundSystem::new(
+            vec![
+                component(two_outputs, "a", &["u"]),
+                component(difference(), "e", &["u", "a"]),
+            ],
+            &[],
+            &[],
+        );
+        let err = Simulation::new(&system.unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dimension mismatch: signals summed by e have different sizes"
+        );
+    }
+
+    #[test]
+    fn gains() {
+        let system = CompoundSystem::new(
+            vec![
+                component(SystemBlock::Gain(array![[2.5]].into()), "a", &["u"]),
+                component(SystemBlock::Gain(array![[1.0], [-1.0]].into()), "b", &["a"]),
+                component(SystemBlock::Gain(array![[0.5]].into()), "c", &["b"]),
+                component(SystemBlock::Gain(array![[1.0, 2.0]].into()), "d", &["c"]),
+            ],
+            &[],
+            &["c".into(), "d".into()],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        assert_eq!(sim.state_size, 0);
+        let output = sim.execute(array![[2.0]].view(), 1).unwrap();
+        assert_relative_eq!(output, array![[2.5, -2.5, -2.5]]);
+    }
+
+    #[test]
+    fn all_signals_and_states_are_recorded() {
+        let integrator = SystemBlock::TransferFunction(Rc::new(
+            DiscreteTransferFunction::new(array![0.0, 1.0], array![1.0, -1.0]).unwrap(),
+        ));
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["u", "y"]),
+                component(integrator, "y", &["e"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        let record = sim
+            .execute_recording(Array2::ones((1, 1)).view(), 4)
+            .unwrap();
+        assert_relative_eq!(record.signal("u").unwrap(), Array2::ones((4, 1)));
+        assert_relative_eq!(
+            record.signal("e").unwrap(),
+            array![[1.0], [0.0], [0.0], [0.0]]
+        );
+        assert_relative_eq!(
+            record.signal("y").unwrap(),
+            array![[0.0], [1.0], [1.0], [1.0]]
+        );
+        assert_relative_eq!(
+            record.state("y").unwrap(),
+            array![[0.0], [1.0], [1.0], [1.0]]
+        );
+        assert_eq!(record.state("e").unwrap().ncols(), 0);
+        assert!(record.signal("x").is_none());
+    }
+
+    #[test]
+    fn algebraic_loop_through_subsystem_is_reported() {
+        let inner = CompoundSystem::new(vec![component(gain(2.0), "y", &["u"])], &[], &[]).unwrap();
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["u", "y"]),
+                component(SystemBlock::SubSystem(Rc::new(inner)), "y", &["e"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap();
+        let err = Simulation::new(&system).unwrap_err();
+        assert_eq!(err.to_string(), "algebraic loop: e -> y -> y.y -> e");
+    }
+
+    #[test]
+    fn algebraic_loop_is_reported() {
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["u", "y"]),
+                component(gain(2.0), "y", &["e"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap();
+        let err = Simulation::new(&system).unwrap_err();
+        assert_eq!(
+            err,
+            Error::AlgebraicLoop(["e".into(), "y".into(), "e".into()].into())
+        );
+    }
+
+    #[test]
+    fn base_rate() {
+        let sampled = |ts| {
+            let tf = DiscreteTransferFunction::new(array![0.0, 1.0], array![1.0, -1.0]).unwrap();
+            SystemBlock::TransferFunction(Rc::new(tf.with_sample_time(ts).unwrap()))
+        };
+        let inner = CompoundSystem::new(vec![component(sampled(0.3), "x", &["u"])], &[], &[]);
+        let inner = SystemBlock::SubSystem(Rc::new(inner.unwrap()));
+        let system = CompoundSystem::new(
+            vec![
+                component(gain(2.0), "a", &["u"]),
+                component(inner, "b", &["a"]),
+                component(sampled(0.2), "c", &["a"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap();
+        assert_relative_eq!(system.sample_time().unwrap(), 0.1, epsilon = 1e-12);
+        let sim = Simulation::new(&system).unwrap();
+        assert_relative_eq!(sim.time(3), array![0.0, 0.1, 0.2], epsilon = 1e-12);
+
+        let err = CompoundSystem::new(
+            vec![
+                component(sampled(0.1), "a", &["u"]),
+                component(sampled(0.1 * std::f64::consts::SQRT_2), "b", &["a"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "sample time mismatch: a runs every 0.1 s and b every 0.141 s, which have no common base rate"
+        );
+    }
+
+    #[test]
+    fn multi_rate_blocks_hold_their_outputs() {
+        let integrator = |ts| {
+            let tf = DiscreteTransferFunction::new(array![0.0, 1.0], array![1.0, -1.0]).unwrap();
+            SystemBlock::TransferFunction(Rc::new(tf.with_sample_time(ts).unwrap()))
+        };
+        let system = CompoundSystem::new(
+            vec![
+                component(integrator(0.1), "fast", &["u"]),
+                component(integrator(0.2), "slow", &["fast"]),
+                component(gain(2.0), "k", &["slow"]),
+            ],
+            &[],
+            &["fast".into(), "slow".into(), "k".into()],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        let output = sim.execute(array![[1.0]].view(), 6).unwrap();
+        // slow samples fast at steps 0, 2 and 4
+        assert_relative_eq!(
+            output.t(),
+            array![
+                [0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+                [0.0, 0.0, 0.0, 0.0, 2.0, 2.0],
+                [0.0, 0.0, 0.0, 0.0, 4.0, 4.0],
+            ]
+        );
+    }
+
+    fn continuous_integrator() -> SystemBlock {
+        SystemBlock::ContinuousTransferFunction(Rc::new(
+            ContinuousTransferFunction::new(array![1.0], array![1.0, 0.0]).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn continuous_states_need_a_solver() {
+        let system = CompoundSystem::new(
+            vec![component(continuous_integrator(), "y", &["u"])],
+            &[],
+            &[],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        assert!(sim.has_continuous_states());
+        assert!(matches!(
+            sim.execute(array![[1.0]].view(), 2),
+            Err(Error::InvalidModel(_))
+        ));
+        let (time, output) = sim
+            .execute_continuous(array![[1.0]].view(), 2, Solver::Rk4 { step: 0.25 })
+            .unwrap();
+        assert_relative_eq!(
+            time,
+            array![0.0, 0.25, 0.5, 0.75, 1.0, 1.0, 1.25, 1.5, 1.75, 2.0]
+        );
+        assert_relative_eq!(output.column(0), time, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn continuous_plant_with_sampled_controller() {
+        let controller = DiscreteTransferFunction::new(array![1.0], array![1.0])
+            .unwrap()
+            .with_sample_time(0.5)
+            .unwrap();
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["u", "y"]),
+                component(
+                    SystemBlock::TransferFunction(Rc::new(controller)),
+                    "c",
+                    &["e"],
+                ),
+                component(continuous_integrator(), "y", &["c"]),
+            ],
+            &[],
+            &["y".into()],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        let (time, output) = sim
+            .execute_continuous(array![[1.0]].view(), 2, Solver::default())
+            .unwrap();
+        // the controller output is held, so y rises linearly between samples
+        let y_at = |t: f64| {
+            let i = time.iter().position(|e| (e - t).abs() < 1e-9).unwrap();
+            output[(i, 0)]
+        };
+        assert_relative_eq!(y_at(0.5), 0.5, epsilon = 1e-9);
+        assert_relative_eq!(y_at(1.0), 0.75, epsilon = 1e-9);
+        for (t, y) in time.iter().zip(output.column(0)) {
+            if *t < 0.5 {
+                assert_relative_eq!(*y, *t, epsilon = 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn jacobian_of_feedback_loop() {
+        let held = DiscreteTransferFunction::new(array![1.0], array![1.0])
+            .unwrap()
+            .with_sample_time(0.5)
+            .unwrap();
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["u", "y"]),
+                component(gain(3.0), "k", &["e"]),
+                component(continuous_integrator(), "y", &["k"]),
+                component(SystemBlock::TransferFunction(Rc::new(held)), "c", &["y"]),
+                component(continuous_integrator(), "z", &["c"]),
+            ],
+            &[],
+            &["y".into(), "z".into()],
+        )
+        .unwrap();
+        let sim = Simulation::new(&system).unwrap();
+        // z only sees the held output of c
+        assert_relative_eq!(sim.jacobian(), array![[-3.0, 0.0], [0.0, 0.0]]);
+        let (time, output) = sim
+            .execute_continuous(
+                array![[1.0]].view(),
+                4,
+                Solver::Rosenbrock {
+                    rel_tol: 1e-6,
+                    abs_tol: 1e-9,
+                    max_step: 1.0,
+                },
+            )
+            .unwrap();
+        assert_eq!(time[time.len() - 1], 2.0);
+        assert_relative_eq!(
+            output[(time.len() - 1, 0)],
+            1.0 - (-6.0f64).exp(),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn feedback_loop_is_linearized() {
+        let plant = DiscreteTransferFunction::new(array![0.0, 0.5], array![1.0, -0.8])
+            .unwrap()
+            .with_sample_time(0.1)
+            .unwrap();
+        let controller =
+            CompoundSystem::new(vec![component(gain(2.0), "k", &["e"])], &["e".into()], &[]);
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["u", "y"]),
+                component(
+                    SystemBlock::SubSystem(Rc::new(controller.unwrap())),
+                    "c",
+                    &["e"],
+                ),
+                component(SystemBlock::TransferFunction(Rc::new(plant)), "y", &["c"]),
+            ],
+            &[],
+            &["y".into(), "e".into()],
+        )
+        .unwrap();
+        let model = system.linearize().unwrap();
+        assert_eq!(model.sample_time(), Some(0.1));
+        assert_eq!((model.input_size(), model.output_size()), (1, 2));
+        // 1 - 0.8 z^-1 + 2 * 0.5 z^-1
+        assert_relative_eq!(model.poles()[0].re, -0.2, epsilon = 1e-12);
+
+        // the model responds like the simulation of the system
+        let linearized = CompoundSystem::new(
+            vec![component(
+                SystemBlock::StateSpace(Rc::new(model)),
+                "y",
+                &["u"],
+            )],
+            &[],
+            &[],
+        )
+        .unwrap();
+        let input = array![[1.0], [0.0], [2.0], [-1.0]];
+        let expected = Simulation::new(&system)
+            .unwrap()
+            .execute(input.view(), 8)
+            .unwrap();
+        let output = Simulation::new(&linearized)
+            .unwrap()
+            .execute(input.view(), 8)
+            .unwrap();
+        assert_relative_eq!(output, expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn linearization_needs_a_single_kind_of_blocks() {
+        let system = CompoundSystem::new(
+            vec![
+                component(difference(), "e", &["u", "y"]),
+                component(gain(3.0), "k", &["e"]),
+                component(continuous_integrator(), "y", &["k"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap();
+        assert!(matches!(system.linearize(), Err(Error::InvalidModel(_))));
+        let model = Simulation::new(&system)
+            .unwrap()
+            .linearize_continuous()
+            .unwrap();
+        assert_relative_eq!(model.a(), array![[-3.0]]);
 
-        Ok(Self { components })
+        let sampled = |ts| {
+            let tf = DiscreteTransferFunction::new(array![0.0, 1.0], array![1.0, -1.0]).unwrap();
+            SystemBlock::TransferFunction(Rc::new(tf.with_sample_time(ts).unwrap()))
+        };
+        let multi_rate = CompoundSystem::new(
+            vec![
+                component(sampled(0.1), "a", &["u"]),
+                component(sampled(0.2), "b", &["a"]),
+            ],
+            &[],
+            &[],
+        )
+        .unwrap();
+        assert!(matches!(
+            multi_rate.linearize(),
+            Err(Error::SampleTimeMismatch(_))
+        ));
+        assert!(matches!(
+            Simulation::new(&multi_rate).unwrap().linearize_continuous(),
+            Err(Error::InvalidModel(_))
+        ));
     }
 }
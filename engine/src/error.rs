@@ -0,0 +1,39 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// Errors while constructing or simulating models
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// Sizes of matrices or signals do not fit together
+    DimensionMismatch(Rc<str>),
+    /// The parameters do not describe a valid model
+    InvalidModel(Rc<str>),
+    /// Blocks of a compound system are connected in an unsupported way
+    InvalidWiring(Rc<str>),
+    UnknownSignal(Rc<str>),
+    DuplicateName(Rc<str>),
+    /// Blocks of a compound system run at different rates
+    SampleTimeMismatch(Rc<str>),
+    /// Blocks with direct feedthrough that depend on each other.
+    /// The first block is repeated at the end.
+    AlgebraicLoop(Rc<[Rc<str>]>),
+    /// The ODE solver could not reach the requested accuracy
+    SolverFailure(Rc<str>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DimensionMismatch(msg) => write!(f, "dimension mismatch: {msg}"),
+            Error::InvalidModel(msg) => write!(f, "invalid model: {msg}"),
+            Error::InvalidWiring(msg) => write!(f, "invalid wiring: {msg}"),
+            Error::UnknownSignal(name) => write!(f, "signal {name} does not exist"),
+            Error::DuplicateName(name) => write!(f, "duplicate name {name}"),
+            Error::SampleTimeMismatch(msg) => write!(f, "sample time mismatch: {msg}"),
+            Error::AlgebraicLoop(names) => write!(f, "algebraic loop: {}", names.join(" -> ")),
+            Error::SolverFailure(msg) => write!(f, "solver failure: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
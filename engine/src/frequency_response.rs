@@ -0,0 +1,566 @@
+//! Frequency responses of transfer functions and state space models
+
+use nalgebra::{Complex, DMatrix};
+use ndarray::prelude::*;
+use std::f64::consts::PI;
+use std::fmt;
+
+use crate::state_space::{ContinuousStateSpaceModel, DiscreteStateSpaceModel};
+use crate::transfer_function::{ContinuousTransferFunction, DiscreteTransferFunction};
+use crate::NiceFloat;
+
+/// Number of frequencies per decade of [`frequency_grid`]
+const POINTS_PER_DECADE: usize = 50;
+
+impl DiscreteTransferFunction {
+    /// Response at z = e^(j w Ts) for the frequencies `w` in rad/s.
+    /// Without a sample time, Ts is one second.
+    pub fn frequency_response(&self, w: ArrayView1<'_, f64>) -> Array1<Complex<f64>> {
+        let ts = self.sample_time().unwrap_or(1.0);
+        w.mapv(|w| self.response_at(Complex::from_polar(1.0, w * ts)))
+    }
+
+    /// Value of the transfer function at `z`
+    pub fn response_at(&self, z: Complex<f64>) -> Complex<f64> {
+        // polynomials in z^-1
+        let z_inv = z.inv();
+        ratio(evaluate(self.num(), z_inv), evaluate(self.den(), z_inv))
+    }
+}
+
+impl ContinuousTransferFunction {
+    /// Response at s = j w for the frequencies `w` in rad/s
+    pub fn frequency_response(&self, w: ArrayView1<'_, f64>) -> Array1<Complex<f64>> {
+        w.mapv(|w| self.response_at(Complex::new(0.0, w)))
+    }
+
+    /// Value of the transfer function at `s`
+    pub fn response_at(&self, s: Complex<f64>) -> Complex<f64> {
+        let (num, den) = (self.num(), self.den());
+        // highest power first
+        ratio(
+            evaluate(num.slice(s![..;-1]), s),
+            evaluate(den.slice(s![..;-1]), s),
+        )
+    }
+}
+
+impl DiscreteStateSpaceModel {
+    /// Response at z = e^(j w Ts) for the frequencies `w` in rad/s,
+    /// indexed by frequency, output and input.
+    /// Without a sample time, Ts is one second.
+    pub fn frequency_response(&self, w: ArrayView1<'_, f64>) -> Array3<Complex<f64>> {
+        let ts = self.sample_time().unwrap_or(1.0);
+        let z = w.iter().map(|w| Complex::from_polar(1.0, w * ts));
+        state_space_response(self.a(), self.b(), self.c(), self.d(), z)
+    }
+
+    /// Transfer matrix at `z`, indexed by output and input
+    pub fn response_at(&self, z: Complex<f64>) -> Array2<Complex<f64>> {
+        state_space_response(self.a(), self.b(), self.c(), self.d(), [z].into_iter())
+            .index_axis_move(Axis(0), 0)
+    }
+}
+
+impl ContinuousStateSpaceModel {
+    /// Response at s = j w for the frequencies `w` in rad/s,
+    /// indexed by frequency, output and input
+    pub fn frequency_response(&self, w: ArrayView1<'_, f64>) -> Array3<Complex<f64>> {
+        let s = w.iter().map(|w| Complex::new(0.0, *w));
+        state_space_response(self.a(), self.b(), self.c(), self.d(), s)
+    }
+
+    /// Transfer matrix at `s`, indexed by output and input
+    pub fn response_at(&self, s: Complex<f64>) -> Array2<Complex<f64>> {
+        state_space_response(self.a(), self.b(), self.c(), self.d(), [s].into_iter())
+            .index_axis_move(Axis(0), 0)
+    }
+}
+
+/// Log-spaced frequencies in rad/s that cover the dynamics of a model.
+///
+/// `roots` are the poles and zeros, in the z-plane for discrete models with
+/// sample time `sample_time`. The grid ends at the Nyquist frequency of
+/// discrete models.
+pub fn frequency_grid(roots: &[Complex<f64>], sample_time: Option<f64>) -> Array1<f64> {
+    let (first, last) = decade_range(roots, sample_time);
+    log_grid(first, last, sample_time)
+}
+
+/// Stability margins of the open loop with frequency response `response`,
+/// closed with negative feedback.
+///
+/// The crossover frequencies are found by root finding, starting from a
+/// grid that extends the [`frequency_grid`] of the `roots` by three decades.
+pub fn stability_margins(
+    response: impl Fn(f64) -> Complex<f64>,
+    roots: &[Complex<f64>],
+    sample_time: Option<f64>,
+) -> StabilityMargins {
+    let (first, last) = decade_range(roots, sample_time);
+    let grid = log_grid(first - 3.0, last + 3.0, sample_time);
+    let values = grid.mapv(&response);
+
+    let mut margins = StabilityMargins {
+        gain_margin: f64::INFINITY,
+        phase_crossover: f64::NAN,
+        phase_margin: f64::INFINITY,
+        gain_crossover: f64::NAN,
+        delay_margin: f64::INFINITY,
+    };
+    // the phase crosses -180 degrees where the response is negative real
+    let imag = |w: f64| response(w).im;
+    let mut candidates = crossings(&grid, &values.mapv(|c| c.im), imag);
+    // at zero and at the Nyquist frequency the response is real, so the
+    // imaginary part does not change its sign there
+    for w in [Some(0.0), sample_time.map(|ts| PI / ts)]
+        .into_iter()
+        .flatten()
+    {
+        let l = response(w);
+        if l.im.abs() <= 1e-9 * l.norm() {
+            candidates.push(w);
+        }
+    }
+    for w in candidates {
+        let l = response(w);
+        let gain_margin = 1.0 / l.norm();
+        if l.re < 0.0 && gain_margin.ln().abs() < margins.gain_margin.ln().abs() {
+            margins.gain_margin = gain_margin;
+            margins.phase_crossover = w;
+        }
+    }
+    let log_gain = |w: f64| response(w).norm().ln();
+    for w in crossings(&grid, &values.mapv(|c| c.norm().ln()), log_gain) {
+        // distance of the phase to -180 degrees, in [0, 2 pi)
+        let lag = (response(w).arg() + PI).rem_euclid(2.0 * PI);
+        let phase_margin = if lag > PI { lag - 2.0 * PI } else { lag }.to_degrees();
+        if phase_margin.abs() < margins.phase_margin.abs() {
+            margins.phase_margin = phase_margin;
+            margins.gain_crossover = w;
+        }
+        // with a negative phase margin the closed loop is already unstable
+        let delay_margin = phase_margin.max(0.0).to_radians() / w;
+        margins.delay_margin = margins.delay_margin.min(delay_margin);
+    }
+    margins
+}
+
+/// Stability margins of an open loop, see [`stability_margins`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StabilityMargins {
+    /// factor by which the loop gain can increase,
+    /// infinite if the phase never crosses -180 degrees
+    pub gain_margin: f64,
+    /// frequency in rad/s at which the phase crosses -180 degrees, NaN if it does not
+    pub phase_crossover: f64,
+    /// additional phase lag in degrees at the gain crossover,
+    /// infinite if the gain never crosses one
+    pub phase_margin: f64,
+    /// frequency in rad/s at which the gain crosses one, NaN if it does not
+    pub gain_crossover: f64,
+    /// additional time delay in seconds that makes the loop unstable,
+    /// zero if the phase margin is negative
+    pub delay_margin: f64,
+}
+
+impl fmt::Display for StabilityMargins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gain margin: {} dB",
+            NiceFloat(20.0 * self.gain_margin.log10())
+        )?;
+        if !self.phase_crossover.is_nan() {
+            write!(f, " at {} rad/s", NiceFloat(self.phase_crossover))?;
+        }
+        write!(f, "\nphase margin: {} deg", NiceFloat(self.phase_margin))?;
+        if !self.gain_crossover.is_nan() {
+            write!(f, " at {} rad/s", NiceFloat(self.gain_crossover))?;
+        }
+        writeln!(f, "\ndelay margin: {} s", NiceFloat(self.delay_margin))
+    }
+}
+
+/// Counts how often the Nyquist curve of the open loop `transfer_function`
+/// encircles the critical point -1 and compares it with the open loop poles
+/// `poles` to decide whether the loop, closed with negative feedback, is stable.
+///
+/// `transfer_function` is evaluated in the s-plane, or in the z-plane for
+/// discrete models with sample time `sample_time`. The contour runs slightly
+/// inside the unstable region, so poles on the stability boundary count as stable.
+pub fn nyquist_stability(
+    transfer_function: impl Fn(Complex<f64>) -> Complex<f64>,
+    poles: &[Complex<f64>],
+    sample_time: Option<f64>,
+) -> NyquistStability {
+    let (first, last) = decade_range(poles, sample_time);
+    let mut grid = log_grid(first - 3.0, last + 3.0, sample_time).to_vec();
+    grid.insert(0, 0.0);
+    let scale = poles.iter().map(|p| p.norm()).fold(1.0, f64::max);
+    let shift = 1e-6 * scale;
+    let (contour, unstable): (Box<dyn Fn(f64) -> Complex<f64>>, _) = match sample_time {
+        Some(ts) => (
+            Box::new(move |w| Complex::from_polar(1.0 + shift, w * ts)),
+            poles.iter().filter(|p| p.norm() > 1.0 + shift).count(),
+        ),
+        None => (
+            Box::new(move |w| Complex::new(shift, w)),
+            poles.iter().filter(|p| p.re > shift).count(),
+        ),
+    };
+    let distance = |w: f64| 1.0 + transfer_function(contour(w));
+    // the curve for negative frequencies is the mirror image, so the total
+    // change of angle is twice the change for positive frequencies
+    let angle: f64 = grid
+        .windows(2)
+        .map(|w| angle_change(&distance, w[0], w[1], distance(w[0]), distance(w[1]), 0))
+        .sum();
+    NyquistStability {
+        encirclements: (2.0 * angle / (2.0 * PI)).round() as i32,
+        unstable_poles: unstable,
+    }
+}
+
+/// Result of [`nyquist_stability`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NyquistStability {
+    /// counterclockwise encirclements of -1 for frequencies from -inf to inf
+    pub encirclements: i32,
+    /// open loop poles in the unstable region
+    pub unstable_poles: usize,
+}
+
+impl NyquistStability {
+    /// Number of closed loop poles in the unstable region
+    pub fn closed_loop_unstable_poles(&self) -> i32 {
+        self.unstable_poles as i32 - self.encirclements
+    }
+}
+
+impl fmt::Display for NyquistStability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "counterclockwise encirclements of -1: {}",
+            self.encirclements
+        )?;
+        writeln!(f, "unstable open loop poles: {}", self.unstable_poles)?;
+        match self.closed_loop_unstable_poles() {
+            0 => writeln!(f, "closed loop is stable"),
+            n => writeln!(f, "closed loop is unstable with {n} unstable poles"),
+        }
+    }
+}
+
+/// Change of the angle of `f` from `a` to `b` where `fa` and `fb` are the
+/// values at the ends. Intervals are split until the angle changes slowly.
+fn angle_change(
+    f: &impl Fn(f64) -> Complex<f64>,
+    a: f64,
+    b: f64,
+    fa: Complex<f64>,
+    fb: Complex<f64>,
+    depth: usize,
+) -> f64 {
+    let change = (fb / fa).arg();
+    if change.abs() < 0.1 || depth >= 60 {
+        return change;
+    }
+    let m = 0.5 * (a + b);
+    let fm = f(m);
+    angle_change(f, a, m, fa, fm, depth + 1) + angle_change(f, m, b, fm, fb, depth + 1)
+}
+
+/// Exponents of the first and last decade that cover the dynamics
+/// of a model, see [`frequency_grid`]
+fn decade_range(roots: &[Complex<f64>], sample_time: Option<f64>) -> (f64, f64) {
+    let natural_frequencies: Vec<f64> = roots
+        .iter()
+        .map(|r| match sample_time {
+            Some(ts) => (r.ln() / ts).norm(),
+            None => r.norm(),
+        })
+        .filter(|w| w.is_finite() && *w > 0.0)
+        .collect();
+    let lowest = natural_frequencies
+        .iter()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    let highest = natural_frequencies.iter().copied().fold(0.0, f64::max);
+    let (mut first, mut last) = if natural_frequencies.is_empty() {
+        (-2.0, 2.0)
+    } else {
+        (lowest.log10().floor() - 1.0, highest.log10().ceil() + 1.0)
+    };
+    if let Some(ts) = sample_time {
+        last = (PI / ts).log10();
+        first = first.min(last - 1.0);
+    }
+    (first, last)
+}
+
+/// Log-spaced frequencies from 10^first to 10^last,
+/// but not beyond the Nyquist frequency
+fn log_grid(first: f64, mut last: f64, sample_time: Option<f64>) -> Array1<f64> {
+    let nyquist = sample_time.map(|ts| PI / ts);
+    if let Some(nyquist) = nyquist {
+        last = last.min(nyquist.log10());
+    }
+    let n = ((last - first) * POINTS_PER_DECADE as f64).ceil() as usize + 1;
+    let mut grid = Array1::logspace(10.0, first, last, n);
+    // avoid rounding beyond the Nyquist frequency
+    if let Some(nyquist) = nyquist {
+        grid[n - 1] = nyquist;
+    }
+    grid
+}
+
+/// Continuous phase in degrees from the angles of `response`
+pub fn unwrapped_phase(response: ArrayView1<'_, Complex<f64>>) -> Array1<f64> {
+    let mut phase = response.mapv(|c| c.arg());
+    for i in 1..phase.len() {
+        let jump = ((phase[i] - phase[i - 1]) / (2.0 * PI)).round();
+        phase[i] -= jump * 2.0 * PI;
+    }
+    phase.mapv(f64::to_degrees)
+}
+
+/// Roots of `f` between grid points where its sign changes.
+/// `values` are the values of `f` on the grid.
+fn crossings(grid: &Array1<f64>, values: &Array1<f64>, f: impl Fn(f64) -> f64) -> Vec<f64> {
+    let mut roots = Vec::new();
+    for i in 1..grid.len() {
+        let (f0, f1) = (values[i - 1], values[i]);
+        if !f0.is_finite() || !f1.is_finite() || (f0 > 0.0) == (f1 > 0.0) {
+            continue;
+        }
+        // bisection until the interval cannot be split anymore
+        let (mut a, mut b) = (grid[i - 1], grid[i]);
+        loop {
+            let m = 0.5 * (a + b);
+            if m <= a || m >= b {
+                break;
+            }
+            if (f(m) > 0.0) == (f0 > 0.0) {
+                a = m;
+            } else {
+                b = m;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    roots
+}
+
+/// sum_i p[i] x^i
+fn evaluate(p: ArrayView1<'_, f64>, x: Complex<f64>) -> Complex<f64> {
+    p.iter()
+        .rev()
+        .fold(Complex::new(0.0, 0.0), |acc, coefficient| {
+            acc * x + coefficient
+        })
+}
+
+/// num / den, infinite at poles
+fn ratio(num: Complex<f64>, den: Complex<f64>) -> Complex<f64> {
+    if den == Complex::new(0.0, 0.0) {
+        Complex::new(f64::INFINITY, 0.0)
+    } else {
+        num / den
+    }
+}
+
+/// c (x I - a)^-1 b + d for every x of `points`
+fn state_space_response(
+    a: ArrayView2<'_, f64>,
+    b: ArrayView2<'_, f64>,
+    c: ArrayView2<'_, f64>,
+    d: ArrayView2<'_, f64>,
+    points: impl ExactSizeIterator<Item = Complex<f64>>,
+) -> Array3<Complex<f64>> {
+    let n = a.nrows();
+    let complex = |m: ArrayView2<'_, f64>| {
+        DMatrix::from_fn(m.nrows(), m.ncols(), |i, j| Complex::new(m[(i, j)], 0.0))
+    };
+    let (a, b, c) = (complex(a), complex(b), complex(c));
+    let mut response = Array3::zeros((points.len(), d.nrows(), d.ncols()));
+    for (k, x) in points.enumerate() {
+        let shifted = DMatrix::identity(n, n) * x - &a;
+        let mut slice = response.index_axis_mut(Axis(0), k);
+        match shifted.lu().solve(&b) {
+            Some(x) => {
+                let h = &c * x;
+                slice.assign(&Array2::from_shape_fn(d.raw_dim(), |(i, j)| {
+                    h[(i, j)] + d[(i, j)]
+                }));
+            }
+            // poles on the unit circle or imaginary axis
+            None => slice.fill(Complex::new(f64::INFINITY, 0.0)),
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn first_order_response() {
+        let tf = ContinuousTransferFunction::new(array![1.0], array![1.0, 1.0]).unwrap();
+        let w = array![0.0, 1.0, 1e3];
+        let response = tf.frequency_response(w.view());
+        assert_relative_eq!(response[0].re, 1.0);
+        assert_relative_eq!(response[1].norm(), 0.5f64.sqrt(), epsilon = 1e-12);
+        assert_relative_eq!(response[1].arg(), -PI / 4.0, epsilon = 1e-12);
+        let ss = tf.convert_to_state_space().unwrap();
+        let ss_response = ss.frequency_response(w.view());
+        assert_eq!(ss_response.shape(), &[3, 1, 1]);
+        for (a, b) in response.iter().zip(ss_response.iter()) {
+            assert_relative_eq!((a - b).norm(), 0.0, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn discrete_response() {
+        // integrator z^-1 / (1 - z^-1) has a pole at w = 0
+        let tf = DiscreteTransferFunction::new(array![0.0, 1.0], array![1.0, -1.0])
+            .unwrap()
+            .with_sample_time(0.1)
+            .unwrap();
+        let w = array![0.0, PI / 0.1];
+        let response = tf.frequency_response(w.view());
+        assert!(response[0].norm().is_infinite());
+        assert_relative_eq!(response[1].re, -0.5, epsilon = 1e-12);
+        let ss = tf.convert_to_state_space().unwrap();
+        let ss_response = ss.frequency_response(w.view());
+        assert!(ss_response[(0, 0, 0)].norm().is_infinite());
+        assert_relative_eq!(ss_response[(1, 0, 0)].re, -0.5, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn grid() {
+        let grid = frequency_grid(&[Complex::new(-1.0, 0.0), Complex::new(-100.0, 0.0)], None);
+        assert_relative_eq!(grid[0], 0.1);
+        assert_relative_eq!(grid[grid.len() - 1], 1000.0, epsilon = 1e-9);
+        assert_eq!(grid.len(), 4 * POINTS_PER_DECADE + 1);
+        let grid = frequency_grid(&[Complex::new(0.5, 0.0)], Some(0.1));
+        assert_eq!(grid[grid.len() - 1], PI / 0.1);
+        assert!(grid[0] < 0.5f64.ln().abs() / 0.1);
+    }
+
+    #[test]
+    fn phase_is_unwrapped() {
+        // third order lag passes -180 degrees
+        let tf = ContinuousTransferFunction::new(array![1.0], array![1.0, 3.0, 3.0, 1.0]).unwrap();
+        let w = Array1::logspace(10.0, -1.0, 2.0, 100);
+        let phase = unwrapped_phase(tf.frequency_response(w.view()).view());
+        assert!(phase.windows(2).into_iter().all(|p| p[1] < p[0]));
+        assert_relative_eq!(phase[99], -3.0 * 100f64.atan().to_degrees(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn margins() {
+        let tf = ContinuousTransferFunction::new(array![2.0], array![1.0, 3.0, 3.0, 1.0]).unwrap();
+        let response = |w| tf.frequency_response(array![w].view())[0];
+        let margins = stability_margins(response, &tf.poles(), None);
+        assert_relative_eq!(margins.phase_crossover, 3f64.sqrt(), epsilon = 1e-9);
+        assert_relative_eq!(margins.gain_margin, 4.0, epsilon = 1e-9);
+        let w = (2f64.powf(2.0 / 3.0) - 1.0).sqrt();
+        let phase_margin = 180.0 - 3.0 * w.atan().to_degrees();
+        assert_relative_eq!(margins.gain_crossover, w, epsilon = 1e-9);
+        assert_relative_eq!(margins.phase_margin, phase_margin, epsilon = 1e-9);
+        assert_relative_eq!(
+            margins.delay_margin,
+            phase_margin.to_radians() / w,
+            epsilon = 1e-9
+        );
+        assert_eq!(
+            margins.to_string(),
+            "gain margin: 12.041 dB at 1.732 rad/s\nphase margin: 67.598 deg at 0.766 rad/s\ndelay margin: 1.539 s\n"
+        );
+
+        let tf = ContinuousTransferFunction::new(array![0.5], array![1.0, 1.0]).unwrap();
+        let response = |w| tf.frequency_response(array![w].view())[0];
+        let margins = stability_margins(response, &tf.poles(), None);
+        assert_eq!(
+            margins.to_string(),
+            "gain margin: inf dB\nphase margin: inf deg\ndelay margin: inf s\n"
+        );
+    }
+
+    #[test]
+    fn margins_at_the_ends_of_the_grid() {
+        let discrete = |num, den| {
+            let tf = DiscreteTransferFunction::new(num, den)
+                .unwrap()
+                .with_sample_time(0.1)
+                .unwrap();
+            stability_margins(
+                |w| tf.frequency_response(array![w].view())[0],
+                &tf.poles(),
+                tf.sample_time(),
+            )
+        };
+        // 2 / (z + 0.5) is -4 at z = -1, the closed loop pole is at -2.5
+        let margins = discrete(array![0.0, 2.0], array![1.0, 0.5]);
+        assert_relative_eq!(margins.gain_margin, 0.25, epsilon = 1e-9);
+        assert_relative_eq!(margins.phase_crossover, PI / 0.1);
+        assert!(margins
+            .to_string()
+            .starts_with("gain margin: -12.041 dB at 31.416 rad/s"));
+        // 1 / (z - 0.5) is -2/3 at z = -1
+        let margins = discrete(array![0.0, 1.0], array![1.0, -0.5]);
+        assert_relative_eq!(margins.gain_margin, 1.5, epsilon = 1e-9);
+        assert_relative_eq!(margins.phase_crossover, PI / 0.1);
+
+        // -2 / (s + 1) is -2 at s = 0
+        let tf = ContinuousTransferFunction::new(array![-2.0], array![1.0, 1.0]).unwrap();
+        let response = |w| tf.frequency_response(array![w].view())[0];
+        let margins = stability_margins(response, &tf.poles(), None);
+        assert_relative_eq!(margins.gain_margin, 0.5, epsilon = 1e-9);
+        assert_eq!(margins.phase_crossover, 0.0);
+        // the gain crosses one with the phase above -180 degrees
+        assert!(margins.phase_margin < 0.0);
+        assert_eq!(margins.delay_margin, 0.0);
+    }
+
+    #[test]
+    fn encirclements() {
+        let continuous = |num: Array1<f64>, den: Array1<f64>| {
+            let tf = ContinuousTransferFunction::new(num, den).unwrap();
+            nyquist_stability(|s| tf.response_at(s), &tf.poles(), None)
+        };
+        // unstable open loop stabilized by feedback
+        let stability = continuous(array![2.0], array![1.0, -1.0]);
+        assert_eq!((stability.encirclements, stability.unstable_poles), (1, 1));
+        assert_eq!(stability.closed_loop_unstable_poles(), 0);
+        // gain margin of eight exceeded
+        let stability = continuous(array![16.0], array![1.0, 3.0, 3.0, 1.0]);
+        assert_eq!((stability.encirclements, stability.unstable_poles), (-2, 0));
+        // the integrator lies on the stability boundary
+        let stability = continuous(array![1.0], array![1.0, 1.0, 0.0]);
+        assert_eq!((stability.encirclements, stability.unstable_poles), (0, 0));
+        assert_eq!(
+            stability.to_string(),
+            "counterclockwise encirclements of -1: 0\nunstable open loop poles: 0\nclosed loop is stable\n"
+        );
+
+        let discrete = |gain: f64| {
+            let tf = DiscreteTransferFunction::new(array![0.0, gain], array![1.0, -1.0])
+                .unwrap()
+                .with_sample_time(0.1)
+                .unwrap();
+            nyquist_stability(|z| tf.response_at(z), &tf.poles(), Some(0.1))
+        };
+        // closed loop poles at 1 - gain
+        assert_eq!(discrete(0.5).closed_loop_unstable_poles(), 0);
+        let stability = discrete(3.0);
+        assert_eq!(stability.closed_loop_unstable_poles(), 1);
+        assert!(stability
+            .to_string()
+            .ends_with("unstable with 1 unstable poles\n"));
+    }
+}
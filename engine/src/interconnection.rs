@@ -0,0 +1,581 @@
+//! Series, parallel and feedback connections of models
+
+use ndarray::prelude::*;
+
+use crate::error::Error;
+use crate::linalg;
+use crate::state_space::{ContinuousStateSpaceModel, DiscreteStateSpaceModel};
+use crate::transfer_function::{ContinuousTransferFunction, DiscreteTransferFunction};
+use crate::NiceFloat;
+
+impl DiscreteTransferFunction {
+    /// `self` followed by `other`, the product of both
+    pub fn series(&self, other: &Self) -> Result<Self, Error> {
+        let (num, den) = series(self.num(), self.den(), other.num(), other.den());
+        self.combined(other, num, den)
+    }
+
+    /// Sum of the outputs of `self` and `other` for the same input
+    pub fn parallel(&self, other: &Self) -> Result<Self, Error> {
+        let (num, den) = parallel(self.num(), self.den(), other.num(), other.den(), false);
+        self.combined(other, num, den)
+    }
+
+    /// `self` times the inverse of `other`
+    pub fn divide(&self, other: &Self) -> Result<Self, Error> {
+        let (num, den) = series(self.num(), self.den(), other.den(), other.num());
+        self.combined(other, num, den)
+    }
+
+    /// Closed loop with `self` in the forward path and `other` in the
+    /// feedback path, which is added to the input with `sign`
+    pub fn feedback(&self, other: &Self, sign: f64) -> Result<Self, Error> {
+        let (num, den) = feedback(
+            self.num(),
+            self.den(),
+            other.num(),
+            other.den(),
+            sign,
+            false,
+        );
+        self.combined(other, num, den)
+    }
+
+    fn combined(&self, other: &Self, num: Array1<f64>, den: Array1<f64>) -> Result<Self, Error> {
+        // delays z^-1 common to both polynomials cancel, otherwise dividing
+        // by a strictly proper model leaves a zero leading coefficient
+        let delays = num
+            .iter()
+            .zip(&den)
+            .take_while(|(n, d)| **n == 0.0 && **d == 0.0)
+            .count();
+        let tf = Self::new(num.slice_move(s![delays..]), den.slice_move(s![delays..]))?;
+        match common_sample_time(self.sample_time(), other.sample_time())? {
+            Some(ts) => tf.with_sample_time(ts),
+            None => Ok(tf),
+        }
+    }
+}
+
+impl ContinuousTransferFunction {
+    /// `self` followed by `other`, the product of both
+    pub fn series(&self, other: &Self) -> Result<Self, Error> {
+        let (num, den) = series(self.num(), self.den(), other.num(), other.den());
+        Self::new(num, den)
+    }
+
+    /// Sum of the outputs of `self` and `other` for the same input
+    pub fn parallel(&self, other: &Self) -> Result<Self, Error> {
+        let (num, den) = parallel(self.num(), self.den(), other.num(), other.den(), true);
+        Self::new(num, den)
+    }
+
+    /// `self` times the inverse of `other`
+    pub fn divide(&self, other: &Self) -> Result<Self, Error> {
+        let (num, den) = series(self.num(), self.den(), other.den(), other.num());
+        Self::new(num, den)
+    }
+
+    /// Closed loop with `self` in the forward path and `other` in the
+    /// feedback path, which is added to the input with `sign`
+    pub fn feedback(&self, other: &Self, sign: f64) -> Result<Self, Error> {
+        let (num, den) = feedback(self.num(), self.den(), other.num(), other.den(), sign, true);
+        Self::new(num, den)
+    }
+}
+
+impl DiscreteStateSpaceModel {
+    /// `self` followed by `other`, the outputs of `self` are the inputs of `other`
+    pub fn series(&self, other: &Self) -> Result<Self, Error> {
+        let connected = Matrices::from(self).series(&Matrices::from(other))?;
+        connected.discrete(common_sample_time(self.sample_time(), other.sample_time())?)
+    }
+
+    /// Sum of the outputs of `self` and `other` for the same inputs
+    pub fn parallel(&self, other: &Self) -> Result<Self, Error> {
+        let connected = Matrices::from(self).parallel(&Matrices::from(other))?;
+        connected.discrete(common_sample_time(self.sample_time(), other.sample_time())?)
+    }
+
+    /// Closed loop with `self` in the forward path and `other` in the
+    /// feedback path, which is added to the inputs with `sign`
+    pub fn feedback(&self, other: &Self, sign: f64) -> Result<Self, Error> {
+        let connected = Matrices::from(self).feedback(&Matrices::from(other), sign)?;
+        connected.discrete(common_sample_time(self.sample_time(), other.sample_time())?)
+    }
+
+    /// Both models side by side, with the inputs and outputs of `self` first
+    pub fn append(&self, other: &Self) -> Result<Self, Error> {
+        let connected = Matrices::from(self).append(&Matrices::from(other));
+        connected.discrete(common_sample_time(self.sample_time(), other.sample_time())?)
+    }
+
+    /// Lower linear fractional transformation: the last outputs of `self` are
+    /// the inputs of `other`, whose outputs are the last inputs of `self`.
+    /// The result maps the remaining inputs to the remaining outputs of `self`.
+    pub fn lft(&self, other: &Self) -> Result<Self, Error> {
+        let connected = Matrices::from(self).lft(&Matrices::from(other))?;
+        connected.discrete(common_sample_time(self.sample_time(), other.sample_time())?)
+    }
+}
+
+impl ContinuousStateSpaceModel {
+    /// `self` followed by `other`, the outputs of `self` are the inputs of `other`
+    pub fn series(&self, other: &Self) -> Result<Self, Error> {
+        Matrices::from(self)
+            .series(&Matrices::from(other))?
+            .continuous()
+    }
+
+    /// Sum of the outputs of `self` and `other` for the same inputs
+    pub fn parallel(&self, other: &Self) -> Result<Self, Error> {
+        Matrices::from(self)
+            .parallel(&Matrices::from(other))?
+            .continuous()
+    }
+
+    /// Closed loop with `self` in the forward path and `other` in the
+    /// feedback path, which is added to the inputs with `sign`
+    pub fn feedback(&self, other: &Self, sign: f64) -> Result<Self, Error> {
+        Matrices::from(self)
+            .feedback(&Matrices::from(other), sign)?
+            .continuous()
+    }
+
+    /// Both models side by side, with the inputs and outputs of `self` first
+    pub fn append(&self, other: &Self) -> Result<Self, Error> {
+        Matrices::from(self)
+            .append(&Matrices::from(other))
+            .continuous()
+    }
+
+    /// Lower linear fractional transformation: the last outputs of `self` are
+    /// the inputs of `other`, whose outputs are the last inputs of `self`.
+    /// The result maps the remaining inputs to the remaining outputs of `self`.
+    pub fn lft(&self, other: &Self) -> Result<Self, Error> {
+        Matrices::from(self)
+            .lft(&Matrices::from(other))?
+            .continuous()
+    }
+}
+
+/// State space matrices, the same for discrete and continuous models
+struct Matrices {
+    a: Array2<f64>,
+    b: Array2<f64>,
+    c: Array2<f64>,
+    d: Array2<f64>,
+}
+
+impl From<&DiscreteStateSpaceModel> for Matrices {
+    fn from(ss: &DiscreteStateSpaceModel) -> Self {
+        let (a, b, c, d) = (ss.a(), ss.b(), ss.c(), ss.d());
+        Self {
+            a: a.to_owned(),
+            b: b.to_owned(),
+            c: c.to_owned(),
+            d: d.to_owned(),
+        }
+    }
+}
+
+impl From<&ContinuousStateSpaceModel> for Matrices {
+    fn from(ss: &ContinuousStateSpaceModel) -> Self {
+        let (a, b, c, d) = (ss.a(), ss.b(), ss.c(), ss.d());
+        Self {
+            a: a.to_owned(),
+            b: b.to_owned(),
+            c: c.to_owned(),
+            d: d.to_owned(),
+        }
+    }
+}
+
+impl Matrices {
+    fn inputs(&self) -> usize {
+        self.d.ncols()
+    }
+    fn outputs(&self) -> usize {
+        self.d.nrows()
+    }
+
+    fn discrete(self, sample_time: Option<f64>) -> Result<DiscreteStateSpaceModel, Error> {
+        let ss = DiscreteStateSpaceModel::new(self.a, self.b, self.c, self.d)?;
+        match sample_time {
+            Some(ts) => ss.with_sample_time(ts),
+            None => Ok(ss),
+        }
+    }
+
+    fn continuous(self) -> Result<ContinuousStateSpaceModel, Error> {
+        ContinuousStateSpaceModel::new(self.a, self.b, self.c, self.d)
+    }
+
+    fn append(&self, other: &Self) -> Self {
+        Self {
+            a: block_diagonal(self.a.view(), other.a.view()),
+            b: block_diagonal(self.b.view(), other.b.view()),
+            c: block_diagonal(self.c.view(), other.c.view()),
+            d: block_diagonal(self.d.view(), other.d.view()),
+        }
+    }
+
+    fn series(&self, other: &Self) -> Result<Self, Error> {
+        let (m1, p1, m2, p2) = (
+            self.inputs(),
+            self.outputs(),
+            other.inputs(),
+            other.outputs(),
+        );
+        if m2 != p1 {
+            return Err(Error::DimensionMismatch(
+                format!("cannot connect {p1} outputs to {m2} inputs in series").into(),
+            ));
+        }
+        let mut loop_gain = Array2::zeros((m1 + m2, p1 + p2));
+        loop_gain.slice_mut(s![m1.., ..p1]).assign(&Array2::eye(p1));
+        let inputs = stack_rows(Array2::eye(m1), Array2::zeros((m2, m1)));
+        let outputs = stack_columns(Array2::zeros((p2, p1)), Array2::eye(p2));
+        self.append(other).connect(loop_gain, inputs, outputs)
+    }
+
+    fn parallel(&self, other: &Self) -> Result<Self, Error> {
+        let (m, p) = (self.inputs(), self.outputs());
+        if (other.inputs(), other.outputs()) != (m, p) {
+            return Err(Error::DimensionMismatch(
+                format!(
+                    "cannot connect models with {m} inputs and {p} outputs and with {} inputs and {} outputs in parallel",
+                    other.inputs(),
+                    other.outputs()
+                )
+                .into(),
+            ));
+        }
+        let inputs = stack_rows(Array2::eye(m), Array2::eye(m));
+        let outputs = stack_columns(Array2::eye(p), Array2::eye(p));
+        self.append(other)
+            .connect(Array2::zeros((2 * m, 2 * p)), inputs, outputs)
+    }
+
+    fn feedback(&self, other: &Self, sign: f64) -> Result<Self, Error> {
+        let (m1, p1, m2, p2) = (
+            self.inputs(),
+            self.outputs(),
+            other.inputs(),
+            other.outputs(),
+        );
+        if m2 != p1 || p2 != m1 {
+            return Err(Error::DimensionMismatch(
+                format!(
+                    "the feedback path needs {p1} inputs and {m1} outputs, but has {m2} inputs and {p2} outputs"
+                )
+                .into(),
+            ));
+        }
+        let mut loop_gain = Array2::zeros((m1 + m2, p1 + p2));
+        loop_gain
+            .slice_mut(s![..m1, p1..])
+            .assign(&(Array2::eye(m1) * sign));
+        loop_gain.slice_mut(s![m1.., ..p1]).assign(&Array2::eye(p1));
+        let inputs = stack_rows(Array2::eye(m1), Array2::zeros((m2, m1)));
+        let outputs = stack_columns(Array2::eye(p1), Array2::zeros((p1, p2)));
+        self.append(other).connect(loop_gain, inputs, outputs)
+    }
+
+    fn lft(&self, other: &Self) -> Result<Self, Error> {
+        let (m1, p1) = (self.inputs(), self.outputs());
+        // measurements go to the inputs of other, its outputs are the controls
+        let (measurements, controls) = (other.inputs(), other.outputs());
+        if measurements > p1 || controls > m1 {
+            return Err(Error::DimensionMismatch(
+                format!(
+                    "a model with {m1} inputs and {p1} outputs cannot be closed with {measurements} inputs and {controls} outputs"
+                )
+                .into(),
+            ));
+        }
+        let (m, p) = (m1 - controls, p1 - measurements);
+        let mut loop_gain = Array2::zeros((m1 + measurements, p1 + controls));
+        loop_gain
+            .slice_mut(s![m..m1, p1..])
+            .assign(&Array2::eye(controls));
+        loop_gain
+            .slice_mut(s![m1.., p..p1])
+            .assign(&Array2::eye(measurements));
+        let inputs = stack_rows(Array2::eye(m), Array2::zeros((m1 - m + measurements, m)));
+        let outputs = stack_columns(Array2::eye(p), Array2::zeros((p, p1 - p + controls)));
+        self.append(other).connect(loop_gain, inputs, outputs)
+    }
+
+    /// Closes the static loop u = `inputs` w + `loop_gain` y and returns the
+    /// model from the external inputs w to the outputs `outputs` y
+    fn connect(
+        &self,
+        loop_gain: Array2<f64>,
+        inputs: Array2<f64>,
+        outputs: Array2<f64>,
+    ) -> Result<Self, Error> {
+        // y = c x + d u = f (c x + d inputs w)
+        let p = self.outputs();
+        let f = linalg::inverse((Array2::eye(p) - self.d.dot(&loop_gain)).view()).ok_or(
+            Error::InvalidModel(
+                "the connection contains an algebraic loop without a solution".into(),
+            ),
+        )?;
+        let fc = f.dot(&self.c);
+        let fd = f.dot(&self.d).dot(&inputs);
+        let bl = self.b.dot(&loop_gain);
+        Ok(Self {
+            a: &self.a + &bl.dot(&fc),
+            b: self.b.dot(&inputs) + bl.dot(&fd),
+            c: outputs.dot(&fc),
+            d: outputs.dot(&fd),
+        })
+    }
+}
+
+fn block_diagonal(x: ArrayView2<'_, f64>, y: ArrayView2<'_, f64>) -> Array2<f64> {
+    let (r, c) = x.dim();
+    let mut m = Array2::zeros((r + y.nrows(), c + y.ncols()));
+    m.slice_mut(s![..r, ..c]).assign(&x);
+    m.slice_mut(s![r.., c..]).assign(&y);
+    m
+}
+
+fn stack_rows(x: Array2<f64>, y: Array2<f64>) -> Array2<f64> {
+    ndarray::concatenate(Axis(0), &[x.view(), y.view()]).unwrap()
+}
+
+fn stack_columns(x: Array2<f64>, y: Array2<f64>) -> Array2<f64> {
+    ndarray::concatenate(Axis(1), &[x.view(), y.view()]).unwrap()
+}
+
+/// Sample time of a connection of two discrete models,
+/// a missing sample time matches any other
+fn common_sample_time(a: Option<f64>, b: Option<f64>) -> Result<Option<f64>, Error> {
+    match (a, b) {
+        (Some(a), Some(b)) if a != b => Err(Error::SampleTimeMismatch(
+            format!(
+                "cannot connect models with sample times {} s and {} s",
+                NiceFloat(a),
+                NiceFloat(b)
+            )
+            .into(),
+        )),
+        _ => Ok(a.or(b)),
+    }
+}
+
+/// n1 n2 / (d1 d2)
+fn series(
+    n1: ArrayView1<'_, f64>,
+    d1: ArrayView1<'_, f64>,
+    n2: ArrayView1<'_, f64>,
+    d2: ArrayView1<'_, f64>,
+) -> (Array1<f64>, Array1<f64>) {
+    (multiply(n1, n2), multiply(d1, d2))
+}
+
+/// (n1 d2 + n2 d1) / (d1 d2)
+fn parallel(
+    n1: ArrayView1<'_, f64>,
+    d1: ArrayView1<'_, f64>,
+    n2: ArrayView1<'_, f64>,
+    d2: ArrayView1<'_, f64>,
+    descending: bool,
+) -> (Array1<f64>, Array1<f64>) {
+    let num = add(multiply(n1, d2).view(), multiply(n2, d1).view(), descending);
+    (num, multiply(d1, d2))
+}
+
+/// n1 d2 / (d1 d2 - sign n1 n2)
+fn feedback(
+    n1: ArrayView1<'_, f64>,
+    d1: ArrayView1<'_, f64>,
+    n2: ArrayView1<'_, f64>,
+    d2: ArrayView1<'_, f64>,
+    sign: f64,
+    descending: bool,
+) -> (Array1<f64>, Array1<f64>) {
+    let loop_num = multiply(n1, n2) * -sign;
+    let den = add(multiply(d1, d2).view(), loop_num.view(), descending);
+    (multiply(n1, d2), den)
+}
+
+/// Product of two polynomials, works for both orders of powers
+fn multiply(a: ArrayView1<'_, f64>, b: ArrayView1<'_, f64>) -> Array1<f64> {
+    Array1::from_vec(linalg::poly_mul(&a.to_vec(), &b.to_vec()))
+}
+
+/// Sum of two polynomials, highest power first if `descending`,
+/// lowest power first otherwise
+fn add<'a>(a: ArrayView1<'a, f64>, b: ArrayView1<'a, f64>, descending: bool) -> Array1<f64> {
+    let len = a.len().max(b.len());
+    let mut sum = Array1::zeros(len);
+    for p in [a, b] {
+        let range = if descending {
+            s![len - p.len()..]
+        } else {
+            s![..p.len()]
+        };
+        sum.slice_mut(range).scaled_add(1.0, &p);
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::Complex;
+
+    /// Random looking MIMO model with `n` states, `m` inputs and `p` outputs
+    fn model(n: usize, m: usize, p: usize, seed: f64) -> DiscreteStateSpaceModel {
+        let value = |i: usize, j: usize, k: usize| ((i * 7 + j * 3 + k) as f64 * seed).sin() * 0.4;
+        DiscreteStateSpaceModel::new(
+            Array2::from_shape_fn((n, n), |(i, j)| value(i, j, 0)),
+            Array2::from_shape_fn((n, m), |(i, j)| value(i, j, 1)),
+            Array2::from_shape_fn((p, n), |(i, j)| value(i, j, 2)),
+            Array2::from_shape_fn((p, m), |(i, j)| value(i, j, 3)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn state_space_connections() {
+        let g = model(3, 2, 2, 0.7);
+        let h = model(2, 2, 2, 1.3);
+        let z = Complex::new(0.4, 0.9);
+        let (gz, hz) = (g.response_at(z), h.response_at(z));
+        let close = |a: Array2<Complex<f64>>, b: Array2<Complex<f64>>| {
+            assert_eq!(a.dim(), b.dim());
+            for (a, b) in a.iter().zip(b.iter()) {
+                assert_relative_eq!((a - b).norm(), 0.0, epsilon = 1e-9);
+            }
+        };
+        close(g.series(&h).unwrap().response_at(z), hz.dot(&gz));
+        close(g.parallel(&h).unwrap().response_at(z), &gz + &hz);
+        // (I + g h)^-1 g
+        let eye = Array2::<Complex<f64>>::eye(2);
+        let loop_gain = &eye + &gz.dot(&hz);
+        let det = loop_gain[(0, 0)] * loop_gain[(1, 1)] - loop_gain[(0, 1)] * loop_gain[(1, 0)];
+        let inverse = array![
+            [loop_gain[(1, 1)], -loop_gain[(0, 1)]],
+            [-loop_gain[(1, 0)], loop_gain[(0, 0)]]
+        ]
+        .mapv(|e| e / det);
+        close(
+            g.feedback(&h, -1.0).unwrap().response_at(z),
+            inverse.dot(&gz),
+        );
+
+        let appended = g.append(&h).unwrap();
+        assert_eq!(
+            (
+                appended.state_size(),
+                appended.input_size(),
+                appended.output_size()
+            ),
+            (5, 4, 4)
+        );
+        assert!(matches!(
+            g.series(&model(1, 3, 1, 0.5)),
+            Err(Error::DimensionMismatch(_))
+        ));
+        assert!(matches!(
+            g.parallel(&model(1, 2, 1, 0.5)),
+            Err(Error::DimensionMismatch(_))
+        ));
+        assert!(matches!(
+            g.feedback(&model(1, 2, 1, 0.5), -1.0),
+            Err(Error::DimensionMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn linear_fractional_transformation() {
+        // plant with inputs [w, u] and outputs [z, y], closed with one controller
+        let plant = model(3, 2, 2, 0.9);
+        let controller = model(1, 1, 1, 0.4);
+        let closed = plant.lft(&controller).unwrap();
+        assert_eq!((closed.input_size(), closed.output_size()), (1, 1));
+        assert_eq!(closed.state_size(), 4);
+        // p11 + p12 k (1 - p22 k)^-1 p21
+        let z = Complex::new(-0.3, 0.5);
+        let (p, k) = (plant.response_at(z), controller.response_at(z)[(0, 0)]);
+        let expected = p[(0, 0)] + p[(0, 1)] * k / (1.0 - p[(1, 1)] * k) * p[(1, 0)];
+        assert_relative_eq!(
+            (closed.response_at(z)[(0, 0)] - expected).norm(),
+            0.0,
+            epsilon = 1e-9
+        );
+        assert!(matches!(
+            controller.lft(&plant),
+            Err(Error::DimensionMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn continuous_algebra() {
+        let g = ContinuousTransferFunction::new(array![1.0], array![1.0, 1.0]).unwrap();
+        let c = ContinuousTransferFunction::new(array![2.0], array![1.0, 0.0]).unwrap();
+        let series = g.series(&c).unwrap();
+        assert_relative_eq!(series.num(), array![0.0, 0.0, 2.0]);
+        assert_relative_eq!(series.den(), array![1.0, 1.0, 0.0]);
+        let parallel = g.parallel(&c).unwrap();
+        assert_relative_eq!(parallel.num(), array![0.0, 3.0, 2.0]);
+        assert_relative_eq!(parallel.den(), array![1.0, 1.0, 0.0]);
+        // 2 / (s^2 + s + 2)
+        let unity = ContinuousTransferFunction::new(array![1.0], array![1.0]).unwrap();
+        let closed = series.feedback(&unity, -1.0).unwrap();
+        assert_relative_eq!(closed.num(), array![0.0, 0.0, 2.0]);
+        assert_relative_eq!(closed.den(), array![1.0, 1.0, 2.0]);
+        // the inverse of a strictly proper model is improper
+        assert!(matches!(unity.divide(&g), Err(Error::InvalidModel(_))));
+    }
+
+    #[test]
+    fn discrete_algebra() {
+        // z^-1 / (1 - 0.5 z^-1) and an integrator 1 / (1 - z^-1)
+        let g = DiscreteTransferFunction::new(array![0.0, 1.0], array![1.0, -0.5])
+            .unwrap()
+            .with_sample_time(0.1)
+            .unwrap();
+        let c = DiscreteTransferFunction::new(array![1.0], array![1.0, -1.0]).unwrap();
+        let unity = DiscreteTransferFunction::new(array![1.0], array![1.0]).unwrap();
+        let closed = g.series(&c).unwrap().feedback(&unity, -1.0).unwrap();
+        assert_eq!(closed.sample_time(), Some(0.1));
+        // the integrator removes any steady state error
+        assert_relative_eq!(closed.dc_gain(), 1.0, epsilon = 1e-12);
+        // common factors are not cancelled, but the values agree
+        let sum = g
+            .parallel(&g.series(&c.divide(&c).unwrap()).unwrap())
+            .unwrap();
+        let z = Complex::new(0.3, 0.8);
+        assert_relative_eq!(
+            (sum.response_at(z) - g.response_at(z) * 2.0).norm(),
+            0.0,
+            epsilon = 1e-12
+        );
+
+        // dividing by a strictly proper plant cancels its delay
+        let plant = DiscreteTransferFunction::new(array![0.0, 0.5, 0.5], array![1.0, -1.5, 0.7])
+            .unwrap()
+            .with_sample_time(0.1)
+            .unwrap();
+        let ratio = plant.divide(&plant).unwrap();
+        assert_relative_eq!((ratio.response_at(z) - 1.0).norm(), 0.0, epsilon = 1e-12);
+        let inverse = g.divide(&g.series(&c).unwrap()).unwrap();
+        assert_relative_eq!(
+            (inverse.response_at(z) - 1.0 / c.response_at(z)).norm(),
+            0.0,
+            epsilon = 1e-12
+        );
+
+        let other = c.with_sample_time(0.2).unwrap();
+        assert!(matches!(
+            g.series(&other),
+            Err(Error::SampleTimeMismatch(_))
+        ));
+    }
+}
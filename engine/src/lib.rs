@@ -1,9 +1,19 @@
 use std::fmt;
 
+pub use nalgebra::Complex;
+
 // pub mod arx;
+pub mod discretization;
 pub mod dynamic_system;
+pub mod error;
+pub mod frequency_response;
+pub mod interconnection;
+mod linalg;
+pub mod ode;
+pub mod root_locus;
 pub mod state_space;
 pub mod transfer_function;
+pub mod zpk;
 
 /// Helper for displaying floats in a certain format
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
@@ -15,6 +25,26 @@ impl fmt::Display for NiceFloat {
         // TODO: do not heap allocate
         let s = format!("{:.3}", self.0);
         let s = s.trim_end_matches('0').trim_end_matches('.');
+        // -0 and negative values that round to zero
+        let s = if s == "-0" { "0" } else { s };
         write!(f, "{s}")
     }
 }
+
+/// Helper for displaying complex numbers like `1 - 2i`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NiceComplex(pub Complex<f64>);
+
+impl fmt::Display for NiceComplex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Complex { re, im } = self.0;
+        if im == 0.0 {
+            write!(f, "{}", NiceFloat(re))
+        } else if re == 0.0 {
+            write!(f, "{}i", NiceFloat(im))
+        } else {
+            let sign = if im < 0.0 { '-' } else { '+' };
+            write!(f, "{} {sign} {}i", NiceFloat(re), NiceFloat(im.abs()))
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Matrix and polynomial helpers on top of nalgebra
+
+use nalgebra::{Complex, DMatrix};
+use ndarray::prelude::*;
+
+pub(crate) fn to_nalgebra(m: ArrayView2<'_, f64>) -> DMatrix<f64> {
+    DMatrix::from_fn(m.nrows(), m.ncols(), |i, j| m[(i, j)])
+}
+
+pub(crate) fn from_nalgebra(m: &DMatrix<f64>) -> Array2<f64> {
+    Array2::from_shape_fn((m.nrows(), m.ncols()), |(i, j)| m[(i, j)])
+}
+
+pub(crate) fn inverse(m: ArrayView2<'_, f64>) -> Option<Array2<f64>> {
+    to_nalgebra(m).try_inverse().map(|inv| from_nalgebra(&inv))
+}
+
+/// Matrix exponential
+pub(crate) fn expm(m: ArrayView2<'_, f64>) -> Array2<f64> {
+    if m.is_empty() {
+        return m.to_owned();
+    }
+    from_nalgebra(&to_nalgebra(m).exp())
+}
+
+/// Principal matrix logarithm by inverse scaling and squaring.
+///
+/// Returns `None` if the matrix has no real logarithm, e.g. because
+/// it is singular or has eigenvalues on the negative real axis.
+pub(crate) fn logm(m: ArrayView2<'_, f64>) -> Option<Array2<f64>> {
+    let n = m.nrows();
+    let id = DMatrix::<f64>::identity(n, n);
+    let mut x = to_nalgebra(m);
+    let mut squarings = 0;
+    while (&x - &id).norm() > 0.25 {
+        if squarings > 60 {
+            return None;
+        }
+        x = sqrtm(&x)?;
+        squarings += 1;
+    }
+    // log(X) = 2 atanh((X - I) (X + I)^-1)
+    let t = (&x - &id) * (&x + &id).try_inverse()?;
+    let t2 = &t * &t;
+    let mut term = t.clone();
+    let mut sum = t;
+    for k in 1..16 {
+        term = &term * &t2;
+        sum += &term / (2 * k + 1) as f64;
+    }
+    let log = sum * 2.0 * 2f64.powi(squarings);
+    log.iter()
+        .all(|e| e.is_finite())
+        .then(|| from_nalgebra(&log))
+}
+
+/// Principal square root by the Denman-Beavers iteration
+fn sqrtm(m: &DMatrix<f64>) -> Option<DMatrix<f64>> {
+    let n = m.nrows();
+    let mut y = m.clone();
+    let mut z = DMatrix::<f64>::identity(n, n);
+    for _ in 0..100 {
+        let y_inv = y.clone().try_inverse()?;
+        let z_inv = z.clone().try_inverse()?;
+        let next_y = (&y + z_inv) / 2.0;
+        z = (&z + y_inv) / 2.0;
+        let converged = (&next_y - &y).norm() <= 1e-14 * next_y.norm();
+        y = next_y;
+        if converged {
+            return Some(y);
+        }
+    }
+    None
+}
+
+pub(crate) fn eigenvalues(a: ArrayView2<'_, f64>) -> Vec<Complex<f64>> {
+    if a.is_empty() {
+        return Vec::new();
+    }
+    to_nalgebra(a)
+        .complex_eigenvalues()
+        .iter()
+        .copied()
+        .collect()
+}
+
+/// Characteristic polynomial det(sI - a), highest power first
+///
+/// Uses the Faddeev-LeVerrier algorithm.
+pub(crate) fn charpoly(a: ArrayView2<'_, f64>) -> Array1<f64> {
+    let n = a.nrows();
+    let mut coefficients = Array1::zeros(n + 1);
+    coefficients[0] = 1.0;
+    let mut m = Array2::<f64>::eye(n);
+    for k in 1..=n {
+        let am = a.dot(&m);
+        coefficients[k] = -am.diag().sum() / k as f64;
+        m = am + Array2::<f64>::eye(n) * coefficients[k];
+    }
+    coefficients
+}
+
+/// Roots of a polynomial, highest power first
+///
+/// Leading zeros are ignored. Roots at zero are exact.
+pub(crate) fn roots(poly: ArrayView1<'_, f64>) -> Vec<Complex<f64>> {
+    let Some(first) = poly.iter().position(|e| *e != 0.0) else {
+        return Vec::new();
+    };
+    let last = poly.iter().rposition(|e| *e != 0.0).unwrap();
+    let mut roots = vec![Complex::new(0.0, 0.0); poly.len() - 1 - last];
+    let order = last - first;
+    if order > 0 {
+        let companion = DMatrix::from_fn(order, order, |i, j| {
+            if i == 0 {
+                -poly[first + j + 1] / poly[first]
+            } else if i == j + 1 {
+                1.0
+            } else {
+                0.0
+            }
+        });
+        roots.extend(companion.complex_eigenvalues().iter());
+    }
+    roots
+}
+
+/// Monic polynomial with the given roots, highest power first
+///
+/// Complex roots are expected to come in conjugate pairs.
+pub(crate) fn poly_from_roots(roots: &[Complex<f64>]) -> Array1<f64> {
+    let mut poly = vec![Complex::new(1.0, 0.0)];
+    for root in roots {
+        poly.push(Complex::new(0.0, 0.0));
+        for i in (1..poly.len()).rev() {
+            let previous = poly[i - 1];
+            poly[i] -= root * previous;
+        }
+    }
+    poly.iter().map(|c| c.re).collect()
+}
+
+/// Product of two polynomials
+pub(crate) fn poly_mul(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut product = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            product[i + j] += x * y;
+        }
+    }
+    product
+}
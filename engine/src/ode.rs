@@ -0,0 +1,388 @@
+//! Solvers for ordinary differential equations dx/dt = f(x)
+
+use nalgebra::{DMatrix, DVector};
+use ndarray::prelude::*;
+
+use crate::error::Error;
+use crate::linalg::to_nalgebra;
+
+/// Integration method for continuous states
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Solver {
+    /// Classic fourth order Runge-Kutta method with a fixed step size in seconds
+    Rk4 { step: f64 },
+    /// Dormand-Prince method of order 5(4) with adaptive step size.
+    /// A step is accepted if the error estimate of every state is below
+    /// `abs_tol + rel_tol * |x|`.
+    Rk45 {
+        rel_tol: f64,
+        abs_tol: f64,
+        max_step: f64,
+    },
+    /// Linearly implicit Rosenbrock method of order 2(3) for stiff systems.
+    /// Uses the same error control as `Rk45`.
+    Rosenbrock {
+        rel_tol: f64,
+        abs_tol: f64,
+        max_step: f64,
+    },
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver::Rk45 {
+            rel_tol: 1e-3,
+            abs_tol: 1e-6,
+            max_step: f64::INFINITY,
+        }
+    }
+}
+
+/// Butcher tableau of the Dormand-Prince method
+const DOPRI_A: [&[f64]; 6] = [
+    &[1. / 5.],
+    &[3. / 40., 9. / 40.],
+    &[44. / 45., -56. / 15., 32. / 9.],
+    &[
+        19372. / 6561.,
+        -25360. / 2187.,
+        64448. / 6561.,
+        -212. / 729.,
+    ],
+    &[
+        9017. / 3168.,
+        -355. / 33.,
+        46732. / 5247.,
+        49. / 176.,
+        -5103. / 18656.,
+    ],
+    &[
+        35. / 384.,
+        0.,
+        500. / 1113.,
+        125. / 192.,
+        -2187. / 6784.,
+        11. / 84.,
+    ],
+];
+/// weights of the fifth order solution minus the fourth order solution
+const DOPRI_ERROR: [f64; 7] = [
+    35. / 384. - 5179. / 57600.,
+    0.,
+    500. / 1113. - 7571. / 16695.,
+    125. / 192. - 393. / 640.,
+    -2187. / 6784. + 92097. / 339200.,
+    11. / 84. - 187. / 2100.,
+    -1. / 40.,
+];
+
+/// Right hand side f of the differential equation dx/dt = f(x)
+pub(crate) trait Ode {
+    fn derivative(&mut self, x: ArrayView1<'_, f64>) -> Array1<f64>;
+
+    /// Jacobian df/dx, by default approximated with forward differences
+    fn jacobian(&mut self, x: ArrayView1<'_, f64>) -> Array2<f64> {
+        let f0 = self.derivative(x);
+        let mut jacobian = Array2::zeros((f0.len(), x.len()));
+        let mut shifted = x.to_owned();
+        for j in 0..x.len() {
+            let delta = f64::EPSILON.sqrt() * x[j].abs().max(1.0);
+            shifted[j] = x[j] + delta;
+            let f = self.derivative(shifted.view());
+            jacobian.column_mut(j).assign(&((f - &f0) / delta));
+            shifted[j] = x[j];
+        }
+        jacobian
+    }
+}
+
+impl<F: FnMut(ArrayView1<'_, f64>) -> Array1<f64>> Ode for F {
+    fn derivative(&mut self, x: ArrayView1<'_, f64>) -> Array1<f64> {
+        self(x)
+    }
+}
+
+/// Advances the solution step by step
+#[derive(Clone, Debug)]
+pub(crate) struct Integrator {
+    solver: Solver,
+    /// next step size of adaptive solvers
+    step_size: f64,
+}
+
+impl Integrator {
+    pub(crate) fn new(solver: Solver) -> Result<Self, Error> {
+        let invalid = |msg: &str| Err(Error::SolverFailure(msg.into()));
+        match solver {
+            Solver::Rk4 { step } if !(step > 0.0 && step.is_finite()) => {
+                return invalid("step size must be positive");
+            }
+            Solver::Rk45 {
+                rel_tol,
+                abs_tol,
+                max_step,
+            }
+            | Solver::Rosenbrock {
+                rel_tol,
+                abs_tol,
+                max_step,
+            } if !(rel_tol > 0.0 && abs_tol > 0.0 && max_step > 0.0) => {
+                return invalid("tolerances and maximum step size must be positive");
+            }
+            _ => {}
+        }
+        Ok(Self {
+            solver,
+            step_size: f64::INFINITY,
+        })
+    }
+
+    /// Advance `x` from `t` by one step, but not beyond `t_end`.
+    /// Returns the new time.
+    pub(crate) fn step(
+        &mut self,
+        ode: &mut impl Ode,
+        t: f64,
+        x: &mut Array1<f64>,
+        t_end: f64,
+    ) -> Result<f64, Error> {
+        // avoid tiny steps caused by rounding errors
+        let remaining = t_end - t;
+        let close_enough = |h: f64| remaining - h <= 1e-9 * h;
+        match self.solver {
+            Solver::Rk4 { step } => {
+                let h = if close_enough(step) { remaining } else { step };
+                let mut f = |x: Array1<f64>| ode.derivative(x.view());
+                let k1 = f(x.clone());
+                let k2 = f(&*x + &(&k1 * (h / 2.0)));
+                let k3 = f(&*x + &(&k2 * (h / 2.0)));
+                let k4 = f(&*x + &(&k3 * h));
+                let next = &*x + &((k1 + k2 * 2.0 + k3 * 2.0 + k4) * (h / 6.0));
+                check_finite(&next, t)?;
+                *x = next;
+                Ok(t + h)
+            }
+            Solver::Rk45 {
+                rel_tol,
+                abs_tol,
+                max_step,
+            } => loop {
+                let mut h = self.step_size.min(max_step);
+                if close_enough(h) {
+                    h = remaining;
+                }
+                let mut k = vec![ode.derivative(x.view())];
+                for a in DOPRI_A {
+                    let mut stage = x.clone();
+                    for (a, k) in a.iter().zip(&k) {
+                        stage.scaled_add(h * a, k);
+                    }
+                    k.push(ode.derivative(stage.view()));
+                }
+                // the last stage is evaluated at the fifth order solution
+                let mut next = x.clone();
+                for (b, k) in DOPRI_A[5].iter().zip(&k) {
+                    next.scaled_add(h * b, k);
+                }
+                let mut error = Array1::zeros(x.len());
+                for (e, k) in DOPRI_ERROR.iter().zip(&k) {
+                    error.scaled_add(h * e, k);
+                }
+                let norm = error_norm(&error, x, &next, rel_tol, abs_tol);
+                if self.adapt(h, norm, 5, t)? {
+                    check_finite(&next, t)?;
+                    *x = next;
+                    return Ok(t + h);
+                }
+            },
+            Solver::Rosenbrock {
+                rel_tol,
+                abs_tol,
+                max_step,
+            } => {
+                // coefficients of the method by Shampine and Reichelt
+                let d = 1.0 / (2.0 + 2f64.sqrt());
+                let e32 = 6.0 + 2f64.sqrt();
+                let f0 = ode.derivative(x.view());
+                let jacobian = to_nalgebra(ode.jacobian(x.view()).view());
+                let n = x.len();
+                loop {
+                    let mut h = self.step_size.min(max_step);
+                    if close_enough(h) {
+                        h = remaining;
+                    }
+                    let w = DMatrix::identity(n, n) - &jacobian * (h * d);
+                    let lu = w.lu();
+                    let solve = |rhs: Array1<f64>| {
+                        let rhs = DVector::from_vec(rhs.to_vec());
+                        lu.solve(&rhs).map(|x| Array1::from_vec(x.data.into()))
+                    };
+                    let Some(k1) = solve(f0.clone()) else {
+                        return Err(Error::SolverFailure(
+                            format!("singular iteration matrix at t = {t}").into(),
+                        ));
+                    };
+                    let f1 = ode.derivative((&*x + &(&k1 * (h / 2.0))).view());
+                    let k2 = solve(&f1 - &k1).unwrap() + &k1;
+                    let next = &*x + &(&k2 * h);
+                    let f2 = ode.derivative(next.view());
+                    let k3 = solve(f2 - (&k2 - &f1) * e32 - (&k1 - &f0) * 2.0).unwrap();
+                    let error = (k1 - k2 * 2.0 + k3) * (h / 6.0);
+                    let norm = error_norm(&error, x, &next, rel_tol, abs_tol);
+                    if self.adapt(h, norm, 3, t)? {
+                        check_finite(&next, t)?;
+                        *x = next;
+                        return Ok(t + h);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Choose the next step size of an adaptive method with error order
+    /// `order` and return whether the current step is accepted
+    fn adapt(&mut self, h: f64, norm: f64, order: i32, t: f64) -> Result<bool, Error> {
+        // NaN would turn into the maximum step size and be accepted
+        if !norm.is_finite() {
+            return Err(diverged(t));
+        }
+        let factor = (0.9 * norm.powf(-1.0 / order as f64)).clamp(0.2, 5.0);
+        self.step_size = h * factor;
+        if norm <= 1.0 {
+            return Ok(true);
+        }
+        if self.step_size <= 1e-12 * t.abs().max(1.0) {
+            return Err(Error::SolverFailure(
+                format!("step size became too small at t = {t}").into(),
+            ));
+        }
+        Ok(false)
+    }
+}
+
+fn diverged(t: f64) -> Error {
+    Error::SolverFailure(format!("the solution diverged at t = {t}").into())
+}
+
+/// Fails if a state has overflowed or become NaN
+fn check_finite(x: &Array1<f64>, t: f64) -> Result<(), Error> {
+    if x.iter().all(|e| e.is_finite()) {
+        Ok(())
+    } else {
+        Err(diverged(t))
+    }
+}
+
+/// Largest error relative to the tolerance of the respective state
+fn error_norm(
+    error: &Array1<f64>,
+    x: &Array1<f64>,
+    next: &Array1<f64>,
+    rel_tol: f64,
+    abs_tol: f64,
+) -> f64 {
+    error
+        .iter()
+        .zip(x.iter().zip(next.iter()))
+        .map(|(e, (a, b))| e.abs() / (abs_tol + rel_tol * a.abs().max(b.abs())))
+        .fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    /// Integrate dx/dt = -x from 0 to 1
+    fn decay(solver: Solver) -> (f64, usize) {
+        let mut integrator = Integrator::new(solver).unwrap();
+        let mut x = array![1.0];
+        let mut t = 0.0;
+        let mut steps = 0;
+        while t < 1.0 {
+            t = integrator
+                .step(&mut |x: ArrayView1<'_, f64>| -&x, t, &mut x, 1.0)
+                .unwrap();
+            steps += 1;
+        }
+        assert_eq!(t, 1.0);
+        (x[0], steps)
+    }
+
+    #[test]
+    fn fixed_step() {
+        let (x, steps) = decay(Solver::Rk4 { step: 0.1 });
+        assert_eq!(steps, 10);
+        assert_relative_eq!(x, (-1.0f64).exp(), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn adaptive_step() {
+        let (x, steps) = decay(Solver::Rk45 {
+            rel_tol: 1e-8,
+            abs_tol: 1e-10,
+            max_step: 1.0,
+        });
+        assert_relative_eq!(x, (-1.0f64).exp(), epsilon = 1e-8);
+        let (_, coarse_steps) = decay(Solver::default());
+        assert!(coarse_steps < steps);
+    }
+
+    #[test]
+    fn stiff_system() {
+        let integrate = |solver| {
+            let mut integrator = Integrator::new(solver).unwrap();
+            let mut f = |x: ArrayView1<'_, f64>| array![-2e4 * x[0], -x[1]];
+            let mut x = array![1.0, 1.0];
+            let mut t = 0.0;
+            let mut steps = 0;
+            while t < 1.0 {
+                t = integrator.step(&mut f, t, &mut x, 1.0).unwrap();
+                steps += 1;
+            }
+            (x, steps)
+        };
+        let tolerances = (1e-4, 1e-6);
+        let (x, implicit_steps) = integrate(Solver::Rosenbrock {
+            rel_tol: tolerances.0,
+            abs_tol: tolerances.1,
+            max_step: 1.0,
+        });
+        assert_relative_eq!(x[0], 0.0, epsilon = 1e-6);
+        assert_relative_eq!(x[1], (-1.0f64).exp(), epsilon = 1e-3);
+        let (_, explicit_steps) = integrate(Solver::Rk45 {
+            rel_tol: tolerances.0,
+            abs_tol: tolerances.1,
+            max_step: 1.0,
+        });
+        // the explicit method is limited by stability, not accuracy
+        assert!(explicit_steps > 5000);
+        assert!(implicit_steps < 100);
+    }
+
+    #[test]
+    fn diverging_solution_fails() {
+        for solver in [
+            Solver::Rk4 { step: 0.1 },
+            Solver::default(),
+            Solver::Rosenbrock {
+                rel_tol: 1e-3,
+                abs_tol: 1e-6,
+                max_step: 1.0,
+            },
+        ] {
+            let mut integrator = Integrator::new(solver).unwrap();
+            let mut f = |x: ArrayView1<'_, f64>| &x * 30.0;
+            let mut x = array![1.0];
+            let mut t = 0.0;
+            let result = loop {
+                match integrator.step(&mut f, t, &mut x, 40.0) {
+                    Ok(t_next) if t_next < 40.0 => t = t_next,
+                    result => break result,
+                }
+            };
+            assert!(matches!(result, Err(Error::SolverFailure(_))));
+            assert!(x[0].is_finite());
+        }
+    }
+}
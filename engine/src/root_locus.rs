@@ -0,0 +1,194 @@
+//! Closed loop poles of an open loop under proportional feedback
+
+use nalgebra::Complex;
+use ndarray::prelude::*;
+
+use crate::linalg;
+use crate::transfer_function::{ContinuousTransferFunction, DiscreteTransferFunction};
+use crate::zpk::z_polynomials;
+
+/// Upper bound of the number of gains of a root locus
+const MAX_STEPS: usize = 5000;
+
+/// Closed loop poles of the open loop L under the feedback u = -K y
+/// for gains K from zero upwards
+#[derive(Clone, Debug, PartialEq)]
+pub struct RootLocus {
+    /// increasing gains, starting at zero
+    pub gains: Array1<f64>,
+    /// closed loop poles, one row per gain and one column per branch
+    pub branches: Array2<Complex<f64>>,
+}
+
+impl DiscreteTransferFunction {
+    /// Root locus in the z-plane
+    pub fn root_locus(&self) -> RootLocus {
+        let (num, den) = z_polynomials(self);
+        root_locus(num, den)
+    }
+}
+
+impl ContinuousTransferFunction {
+    /// Root locus in the s-plane
+    pub fn root_locus(&self) -> RootLocus {
+        root_locus(self.num(), self.den())
+    }
+}
+
+/// Roots of den + K num with polynomials highest power first.
+///
+/// The gain step adapts so that no root moves by more than a small fraction
+/// of the size of the pole zero pattern, which keeps the branches smooth near
+/// breakaway points. The locus ends when every branch is close to a zero or
+/// far away from all poles and zeros.
+fn root_locus(num: ArrayView1<'_, f64>, den: ArrayView1<'_, f64>) -> RootLocus {
+    // align the powers of both polynomials
+    let len = num.len().max(den.len());
+    let pad = |p: ArrayView1<'_, f64>| {
+        let mut padded = Array1::zeros(len);
+        padded.slice_mut(s![len - p.len()..]).assign(&p);
+        padded
+    };
+    let (num, den) = (pad(num), pad(den));
+    let poles = linalg::roots(den.view());
+    let zeros = linalg::roots(num.view());
+    let size = poles
+        .iter()
+        .chain(&zeros)
+        .map(|r| r.norm())
+        .fold(1.0, f64::max);
+    let max_move = 0.02 * size;
+    let norm = |p: &Array1<f64>| p.iter().map(|c| c * c).sum::<f64>().sqrt();
+    // gain at which both polynomials have a similar influence
+    let gain_scale = norm(&den) / norm(&num);
+
+    let mut gains = vec![0.0];
+    let mut branches = vec![poles.clone()];
+    if !gain_scale.is_finite() {
+        return collect(gains, branches);
+    }
+    let finished = |roots: &[Complex<f64>]| {
+        roots
+            .iter()
+            .all(|r| r.norm() > 3.0 * size || zeros.iter().any(|z| (r - z).norm() < 0.01 * size))
+    };
+    let mut step = 1e-3 * gain_scale;
+    while gains.len() < MAX_STEPS {
+        let previous = branches.last().unwrap();
+        let gain = gains.last().unwrap() + step;
+        let roots = linalg::roots((&den + &(&num * gain)).view());
+        // the degree drops where the leading coefficients cancel
+        if roots.len() != previous.len() {
+            step *= 1.5;
+            continue;
+        }
+        let roots = match_roots(previous, roots);
+        let movement = previous
+            .iter()
+            .zip(&roots)
+            .map(|(a, b)| (a - b).norm() / a.norm().max(size))
+            .fold(0.0, f64::max)
+            * size;
+        if movement > max_move && step > 1e-12 * gain_scale {
+            step /= 2.0;
+            continue;
+        }
+        if movement < max_move / 4.0 {
+            step *= 2.0;
+        }
+        let done = finished(&roots);
+        gains.push(gain);
+        branches.push(roots);
+        if done {
+            break;
+        }
+    }
+    collect(gains, branches)
+}
+
+/// `roots` ordered such that each is close to the root of `previous`
+/// at the same position
+fn match_roots(previous: &[Complex<f64>], mut roots: Vec<Complex<f64>>) -> Vec<Complex<f64>> {
+    let mut pairs: Vec<(usize, usize)> = (0..previous.len())
+        .flat_map(|i| (0..roots.len()).map(move |j| (i, j)))
+        .collect();
+    pairs.sort_by(|(i, j), (k, l)| {
+        (previous[*i] - roots[*j])
+            .norm()
+            .total_cmp(&(previous[*k] - roots[*l]).norm())
+    });
+    let mut assigned = vec![None; previous.len()];
+    let mut used = vec![false; roots.len()];
+    for (i, j) in pairs {
+        if assigned[i].is_none() && !used[j] {
+            assigned[i] = Some(j);
+            used[j] = true;
+        }
+    }
+    let order: Vec<_> = assigned.into_iter().map(Option::unwrap).collect();
+    let original = roots.clone();
+    for (root, j) in roots.iter_mut().zip(order) {
+        *root = original[j];
+    }
+    roots
+}
+
+fn collect(gains: Vec<f64>, branches: Vec<Vec<Complex<f64>>>) -> RootLocus {
+    let n = branches[0].len();
+    let branches = Array2::from_shape_vec(
+        (branches.len(), n),
+        branches.into_iter().flatten().collect(),
+    )
+    .unwrap();
+    RootLocus {
+        gains: Array1::from_vec(gains),
+        branches,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn second_order_breakaway() {
+        // 1 / (s (s + 2)) breaks away from the real axis at -1 for K = 1
+        let tf =
+            ContinuousTransferFunction::new(array![0.0, 0.0, 1.0], array![1.0, 2.0, 0.0]).unwrap();
+        let locus = tf.root_locus();
+        assert_eq!(locus.gains[0], 0.0);
+        assert!(locus.gains.windows(2).into_iter().all(|k| k[1] > k[0]));
+        assert_eq!(locus.branches.ncols(), 2);
+        for (gain, roots) in locus.gains.iter().zip(locus.branches.rows()) {
+            // closed loop poles -1 +- sqrt(1 - K)
+            let expected = Complex::new(1.0 - gain, 0.0).sqrt();
+            for r in roots {
+                assert_relative_eq!((r + 1.0).norm(), expected.norm(), epsilon = 1e-6);
+            }
+        }
+        // branches are continuous, also through the breakaway point
+        let rows: Vec<_> = locus.branches.rows().into_iter().collect();
+        for pair in rows.windows(2) {
+            for (a, b) in pair[0].iter().zip(pair[1].iter()) {
+                assert!((a - b).norm() < 0.1);
+            }
+        }
+        // both branches leave along the asymptotes at -1
+        let last = locus.branches.row(locus.branches.nrows() - 1);
+        assert!(last
+            .iter()
+            .all(|r| r.norm() > 6.0 && (r.re + 1.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn discrete_branch_ends_at_zero() {
+        // (z - 0.5) / (z - 1)^2 in powers of z^-1
+        let tf =
+            DiscreteTransferFunction::new(array![0.0, 1.0, -0.5], array![1.0, -2.0, 1.0]).unwrap();
+        let locus = tf.root_locus();
+        let last = locus.branches.row(locus.branches.nrows() - 1);
+        assert!(last.iter().any(|r| (r - 0.5).norm() < 0.02));
+        assert!(last.iter().any(|r| r.norm() > 3.0));
+    }
+}
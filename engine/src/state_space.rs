@@ -2,6 +2,9 @@ use ndarray::prelude::*;
 use ndarray::Data;
 use std::fmt;
 
+use crate::error::Error;
+use crate::NiceFloat;
+
 /// Discrete Time MIMO State Space Model
 ///
 /// x_(k+1) = a * x_k + b * u_k
@@ -10,6 +13,8 @@ use std::fmt;
 pub struct DiscreteStateSpaceModel {
     data: Array2<f64>,
     n: usize,
+    /// time between two steps in seconds, `None` if not specified
+    sample_time: Option<f64>,
 }
 
 impl DiscreteStateSpaceModel {
@@ -23,19 +28,23 @@ impl DiscreteStateSpaceModel {
         b: ArrayBase<S2, Ix2>,
         c: ArrayBase<S3, Ix2>,
         d: ArrayBase<S4, Ix2>,
-    ) -> Self {
-        let n = a.nrows();
-        let m = b.ncols();
-        let r = c.nrows();
-        if a.ncols() != n || b.nrows() != n || c.ncols() != n || d.ncols() != m || d.nrows() != r {
-            panic!();
-        }
-        let mut data = Array2::zeros([n + r, n + m]);
-        data.slice_mut(s![..n, ..n]).assign(&a);
-        data.slice_mut(s![..n, n..]).assign(&b);
-        data.slice_mut(s![n.., ..n]).assign(&c);
-        data.slice_mut(s![n.., n..]).assign(&d);
-        Self { data, n }
+    ) -> Result<Self, Error> {
+        let (data, n) = assemble(a, b, c, d)?;
+        Ok(Self {
+            data,
+            n,
+            sample_time: None,
+        })
+    }
+
+    pub fn with_sample_time(mut self, ts: f64) -> Result<Self, Error> {
+        check_sample_time(ts)?;
+        self.sample_time = Some(ts);
+        Ok(self)
+    }
+
+    pub fn sample_time(&self) -> Option<f64> {
+        self.sample_time
     }
 
     pub fn state_size(&self) -> usize {
@@ -90,6 +99,180 @@ impl fmt::Display for DiscreteStateSpaceModel {
         write!(f, "B: {} ", self.b())?;
         write!(f, "C: {} ", self.c())?;
         write!(f, "D: {} ", self.d())?;
+        if let Some(ts) = self.sample_time {
+            write!(f, "Ts: {} ", NiceFloat(ts))?;
+        }
+        Ok(())
+    }
+}
+
+/// Continuous Time MIMO State Space Model
+///
+/// dx/dt = a * x + b * u
+/// y = c * x + d * u
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContinuousStateSpaceModel {
+    data: Array2<f64>,
+    n: usize,
+}
+
+impl ContinuousStateSpaceModel {
+    pub fn new<
+        S1: Data<Elem = f64>,
+        S2: Data<Elem = f64>,
+        S3: Data<Elem = f64>,
+        S4: Data<Elem = f64>,
+    >(
+        a: ArrayBase<S1, Ix2>,
+        b: ArrayBase<S2, Ix2>,
+        c: ArrayBase<S3, Ix2>,
+        d: ArrayBase<S4, Ix2>,
+    ) -> Result<Self, Error> {
+        let (data, n) = assemble(a, b, c, d)?;
+        Ok(Self { data, n })
+    }
+
+    pub fn state_size(&self) -> usize {
+        self.n
+    }
+    pub fn input_size(&self) -> usize {
+        self.data.ncols() - self.n
+    }
+    pub fn output_size(&self) -> usize {
+        self.data.nrows() - self.n
+    }
+
+    pub fn a(&self) -> ArrayView2<'_, f64> {
+        self.data.slice(s![..self.n, ..self.n])
+    }
+    pub fn b(&self) -> ArrayView2<'_, f64> {
+        self.data.slice(s![..self.n, self.n..])
+    }
+    pub fn c(&self) -> ArrayView2<'_, f64> {
+        self.data.slice(s![self.n.., ..self.n])
+    }
+    pub fn d(&self) -> ArrayView2<'_, f64> {
+        self.data.slice(s![self.n.., self.n..])
+    }
+
+    /// dx/dt for the given input and state
+    pub fn derivative(
+        &self,
+        input: ArrayView1<'_, f64>,
+        state: ArrayView1<'_, f64>,
+        mut derivative: ArrayViewMut1<'_, f64>,
+    ) {
+        derivative.assign(&(self.a().dot(&state) + self.b().dot(&input)));
+    }
+
+    pub fn calculate_output(&self, state: ArrayView1<'_, f64>, mut output: ArrayViewMut1<'_, f64>) {
+        output.assign(&self.c().dot(&state));
+    }
+
+    pub fn calculate_output_with_feedthrough(
+        &self,
+        input: ArrayView1<'_, f64>,
+        state: ArrayView1<'_, f64>,
+        mut output: ArrayViewMut1<'_, f64>,
+    ) {
+        output.assign(&(self.c().dot(&state) + self.d().dot(&input)));
+    }
+
+    pub fn has_feedthrough(&self) -> bool {
+        self.d().iter().any(|e| *e != 0.0)
+    }
+}
+
+impl fmt::Display for ContinuousStateSpaceModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "A: {} ", self.a())?;
+        write!(f, "B: {} ", self.b())?;
+        write!(f, "C: {} ", self.c())?;
+        write!(f, "D: {} ", self.d())?;
+        Ok(())
+    }
+}
+
+/// Sample times have to be positive and finite
+pub(crate) fn check_sample_time(ts: f64) -> Result<(), Error> {
+    if ts > 0.0 && ts.is_finite() {
         Ok(())
+    } else {
+        Err(Error::InvalidModel(
+            format!("sample time must be positive, but is {ts}").into(),
+        ))
+    }
+}
+
+/// Check the dimensions of a, b, c, d and store them in one matrix
+/// `[[a, b], [c, d]]`. Also returns the number of states.
+fn assemble<
+    S1: Data<Elem = f64>,
+    S2: Data<Elem = f64>,
+    S3: Data<Elem = f64>,
+    S4: Data<Elem = f64>,
+>(
+    a: ArrayBase<S1, Ix2>,
+    b: ArrayBase<S2, Ix2>,
+    c: ArrayBase<S3, Ix2>,
+    d: ArrayBase<S4, Ix2>,
+) -> Result<(Array2<f64>, usize), Error> {
+    let n = a.nrows();
+    let m = b.ncols();
+    let r = c.nrows();
+    let mismatch = |msg: String| Err(Error::DimensionMismatch(msg.into()));
+    if a.ncols() != n {
+        return mismatch(format!("A must be square, but is {}x{}", n, a.ncols()));
+    }
+    if b.nrows() != n {
+        return mismatch(format!("B has {} rows, but A has {n}", b.nrows()));
+    }
+    if c.ncols() != n {
+        return mismatch(format!("C has {} columns, but A has {n}", c.ncols()));
+    }
+    if d.nrows() != r || d.ncols() != m {
+        return mismatch(format!(
+            "D is {}x{}, but C and B require {r}x{m}",
+            d.nrows(),
+            d.ncols()
+        ));
+    }
+    let mut data = Array2::zeros([n + r, n + m]);
+    data.slice_mut(s![..n, ..n]).assign(&a);
+    data.slice_mut(s![..n, n..]).assign(&b);
+    data.slice_mut(s![n.., ..n]).assign(&c);
+    data.slice_mut(s![n.., n..]).assign(&d);
+    Ok((data, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatched_dimensions() {
+        let err = DiscreteStateSpaceModel::new(
+            Array2::<f64>::zeros((2, 2)),
+            Array2::<f64>::zeros((3, 1)),
+            Array2::<f64>::zeros((1, 2)),
+            Array2::<f64>::zeros((1, 1)),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dimension mismatch: B has 3 rows, but A has 2"
+        );
+
+        let err = DiscreteStateSpaceModel::new(
+            Array2::<f64>::zeros((2, 2)),
+            Array2::<f64>::zeros((2, 1)),
+            Array2::<f64>::zeros((1, 2)),
+            Array2::<f64>::zeros((2, 1)),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dimension mismatch: D is 2x1, but C and B require 1x1"
+        );
     }
 }
@@ -2,25 +2,37 @@ use ndarray::prelude::*;
 use std::fmt;
 use std::fmt::Write;
 
-use crate::{state_space::DiscreteStateSpaceModel, NiceFloat};
+use crate::error::Error;
+use crate::linalg;
+use crate::state_space::{check_sample_time, ContinuousStateSpaceModel, DiscreteStateSpaceModel};
+use crate::NiceFloat;
 
 /// Discrete Time Transfer Function
 ///
-/// Invariant: `num.len() > 0 && den.len() == num.len()`
+/// Invariant: `num.len() > 0 && den.len() == num.len() && den[0] != 0`
 #[derive(Clone, Debug, PartialEq)]
 pub struct DiscreteTransferFunction {
     /// numerator polynomial.
     /// num[i] is the coefficient for z^(-i)
     num: Array1<f64>,
-    /// numerator polynomial.
+    /// denominator polynomial.
     /// den[j] is the coefficient for z^(-j)
     den: Array1<f64>,
+    /// time between two steps in seconds, `None` if not specified
+    sample_time: Option<f64>,
 }
 
 impl DiscreteTransferFunction {
-    pub fn new(mut num: Array1<f64>, mut den: Array1<f64>) -> Option<Self> {
+    pub fn new(mut num: Array1<f64>, mut den: Array1<f64>) -> Result<Self, Error> {
         if num.is_empty() || den.is_empty() {
-            return None;
+            return Err(Error::InvalidModel(
+                "numerator and denominator must not be empty".into(),
+            ));
+        }
+        if den[0] == 0. {
+            return Err(Error::InvalidModel(
+                "leading denominator coefficient must not be zero".into(),
+            ));
         }
         let num_len = num.len();
         let den_len = den.len();
@@ -32,96 +44,273 @@ impl DiscreteTransferFunction {
             den.append(Axis(0), Array::zeros(num_len - den_len).view())
                 .unwrap();
         }
-        Some(Self { num, den })
+        Ok(Self {
+            num,
+            den,
+            sample_time: None,
+        })
+    }
+
+    pub fn with_sample_time(mut self, ts: f64) -> Result<Self, Error> {
+        check_sample_time(ts)?;
+        self.sample_time = Some(ts);
+        Ok(self)
+    }
+
+    pub fn sample_time(&self) -> Option<f64> {
+        self.sample_time
+    }
+
+    /// Transfer function of a single input single output model
+    pub fn from_state_space(ss: &DiscreteStateSpaceModel) -> Result<Self, Error> {
+        let (num, den) = siso_coefficients(ss.a(), ss.b(), ss.c(), ss.d())?;
+        Ok(Self {
+            sample_time: ss.sample_time(),
+            ..Self::new(num, den)?
+        })
+    }
+
+    pub fn num(&self) -> ArrayView1<'_, f64> {
+        self.num.view()
+    }
+    pub fn den(&self) -> ArrayView1<'_, f64> {
+        self.den.view()
+    }
+
+    pub fn convert_to_state_space(&self) -> Result<DiscreteStateSpaceModel, Error> {
+        let (a, b, c, d) = controllable_canonical_form(self.num.view(), self.den.view())?;
+        let ss = DiscreteStateSpaceModel::new(a, b, c, d)?;
+        match self.sample_time {
+            Some(ts) => ss.with_sample_time(ts),
+            None => Ok(ss),
+        }
     }
+}
 
-    pub fn convert_to_state_space(&self) -> Option<DiscreteStateSpaceModel> {
-        let d0 = self.den[0]; // normalization coeff
-        if d0 == 0. {
-            return None;
+impl fmt::Display for DiscreteTransferFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let monomial = |i| (i > 0).then(|| format!("z^-{i}"));
+        let num = format_poly(self.num.view(), monomial)?;
+        let den_is_one = self.den[0] == 1.0 && self.den.iter().skip(1).all(|e| *e == 0.0);
+        let den = format_poly(self.den.view(), monomial)?;
+        format_fraction(f, &num, (!den_is_one).then_some(&den))?;
+        if let Some(ts) = self.sample_time {
+            writeln!(f, "Ts = {}", NiceFloat(ts))?;
         }
-        let order = self.den.len() - 1;
-        let n0 = self.num[0];
-
-        let mut a = Array2::zeros([order, order]);
-        let mut b = Array1::zeros(order);
-        let mut c = Array1::from_iter(self.den.iter().skip(1).map(|di| -di / d0 * n0 / d0));
-        let d = Array0::from_elem([], n0 / d0);
-
-        if order > 0 {
-            a.row_mut(0)
-                .iter_mut()
-                .zip(self.den.iter().skip(1))
-                .for_each(|(el, di)| *el = -di / d0);
-            a.slice_mut(s![1.., ..order])
-                .diag_mut()
-                .mapv_inplace(|_| 1.0);
-
-            b[0] = 1.;
-
-            c.iter_mut()
-                .zip(self.num.iter().skip(1))
-                .for_each(|(el, ni)| *el += ni / d0);
+        Ok(())
+    }
+}
+
+/// Continuous Time Transfer Function
+///
+/// Invariant: `num.len() > 0 && den.len() == num.len() && den[0] != 0`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContinuousTransferFunction {
+    /// numerator polynomial.
+    /// num[i] is the coefficient for s^(n-i), where n is the order
+    num: Array1<f64>,
+    /// denominator polynomial.
+    /// den[j] is the coefficient for s^(n-j), where n is the order
+    den: Array1<f64>,
+}
+
+impl ContinuousTransferFunction {
+    /// Coefficients are given in descending powers of s
+    pub fn new(num: Array1<f64>, den: Array1<f64>) -> Result<Self, Error> {
+        let strip = |p: Array1<f64>| match p.iter().position(|e| *e != 0.0) {
+            Some(first) => p.slice(s![first..]).to_owned(),
+            None => Array1::zeros(0),
+        };
+        let den = strip(den);
+        if den.is_empty() {
+            return Err(Error::InvalidModel("denominator must not be zero".into()));
         }
+        let num = strip(num);
+        if num.len() > den.len() {
+            return Err(Error::InvalidModel(
+                "improper transfer function, the numerator has a higher degree than the denominator"
+                    .into(),
+            ));
+        }
+        let mut padded_num = Array1::zeros(den.len());
+        padded_num
+            .slice_mut(s![den.len() - num.len()..])
+            .assign(&num);
+        Ok(Self {
+            num: padded_num,
+            den,
+        })
+    }
 
-        // Matlab uses a rescaling step (prescale) here with diagonal T were the elements are powers of 2
-        //     A' = inv(T) * A * T
-        //     B' = inv(T) * B
-        //     C' = C * T
+    /// Transfer function of a single input single output model
+    pub fn from_state_space(ss: &ContinuousStateSpaceModel) -> Result<Self, Error> {
+        let (num, den) = siso_coefficients(ss.a(), ss.b(), ss.c(), ss.d())?;
+        Self::new(num, den)
+    }
+
+    pub fn num(&self) -> ArrayView1<'_, f64> {
+        self.num.view()
+    }
+    pub fn den(&self) -> ArrayView1<'_, f64> {
+        self.den.view()
+    }
 
-        Some(DiscreteStateSpaceModel::new(
-            a,
-            b.insert_axis(Axis(1)),
-            c.insert_axis(Axis(0)),
-            d.insert_axis(Axis(0)).insert_axis(Axis(0)),
-        ))
+    pub fn convert_to_state_space(&self) -> Result<ContinuousStateSpaceModel, Error> {
+        let (a, b, c, d) = controllable_canonical_form(self.num.view(), self.den.view())?;
+        ContinuousStateSpaceModel::new(a, b, c, d)
     }
 }
 
-impl fmt::Display for DiscreteTransferFunction {
+impl fmt::Display for ContinuousTransferFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fn format_poly(vals: ArrayView1<'_, f64>) -> Result<String, fmt::Error> {
-            let mut out = String::new();
-            let mut written = false;
-            for (i, el) in vals.iter().enumerate() {
-                if *el == 0.0 {
-                    continue;
-                }
-                if *el != 1.0 || i == 0 {
-                    if written {
-                        if *el < 0. {
-                            write!(out, " - {}", NiceFloat(el.abs()))?;
-                        } else {
-                            write!(out, " + {}", NiceFloat(el.abs()))?;
-                        }
-                    } else {
-                        write!(out, "{}", NiceFloat(*el))?;
-                    }
-                }
-                if i > 0 {
-                    write!(out, " z^-{}", i)?;
-                }
-                written = true;
+        let order = self.den.len() - 1;
+        let monomial = |i| match order - i {
+            0 => None,
+            1 => Some("s".to_string()),
+            p => Some(format!("s^{p}")),
+        };
+        let num = format_poly(self.num.view(), monomial)?;
+        let den_is_one = order == 0 && self.den[0] == 1.0;
+        let den = format_poly(self.den.view(), monomial)?;
+        format_fraction(f, &num, (!den_is_one).then_some(&den))
+    }
+}
+
+/// A, B, C and D
+type StateSpaceMatrices = (Array2<f64>, Array2<f64>, Array2<f64>, Array2<f64>);
+
+/// State space matrices of the controllable canonical form.
+///
+/// `num` and `den` have the same length, with the highest power first.
+/// This is the same for polynomials in s and in z^-1.
+fn controllable_canonical_form(
+    num: ArrayView1<'_, f64>,
+    den: ArrayView1<'_, f64>,
+) -> Result<StateSpaceMatrices, Error> {
+    let d0 = den[0]; // normalization coeff
+    if d0 == 0. {
+        return Err(Error::InvalidModel(
+            "leading denominator coefficient must not be zero".into(),
+        ));
+    }
+    let order = den.len() - 1;
+    let n0 = num[0];
+
+    let mut a = Array2::zeros([order, order]);
+    let mut b = Array1::zeros(order);
+    let mut c = Array1::from_iter(den.iter().skip(1).map(|di| -di / d0 * n0 / d0));
+    let d = Array0::from_elem([], n0 / d0);
+
+    if order > 0 {
+        a.row_mut(0)
+            .iter_mut()
+            .zip(den.iter().skip(1))
+            .for_each(|(el, di)| *el = -di / d0);
+        a.slice_mut(s![1.., ..order])
+            .diag_mut()
+            .mapv_inplace(|_| 1.0);
+
+        b[0] = 1.;
+
+        c.iter_mut()
+            .zip(num.iter().skip(1))
+            .for_each(|(el, ni)| *el += ni / d0);
+    }
+
+    // Matlab uses a rescaling step (prescale) here with diagonal T were the elements are powers of 2
+    //     A' = inv(T) * A * T
+    //     B' = inv(T) * B
+    //     C' = C * T
+
+    Ok((
+        a,
+        b.insert_axis(Axis(1)),
+        c.insert_axis(Axis(0)),
+        d.insert_axis(Axis(0)).insert_axis(Axis(0)),
+    ))
+}
+
+/// Numerator and denominator of c (sI - a)^-1 b + d, highest power first.
+///
+/// Uses det(sI - a + b c) = det(sI - a) (1 + c (sI - a)^-1 b).
+/// The same holds for z instead of s.
+fn siso_coefficients(
+    a: ArrayView2<'_, f64>,
+    b: ArrayView2<'_, f64>,
+    c: ArrayView2<'_, f64>,
+    d: ArrayView2<'_, f64>,
+) -> Result<(Array1<f64>, Array1<f64>), Error> {
+    if b.ncols() != 1 || c.nrows() != 1 {
+        return Err(Error::DimensionMismatch(
+            format!(
+                "a transfer function needs a single input and output, but the model has {} inputs and {} outputs",
+                b.ncols(),
+                c.nrows()
+            )
+            .into(),
+        ));
+    }
+    let den = linalg::charpoly(a);
+    let closed = linalg::charpoly((&a - &b.dot(&c)).view());
+    let num = &closed - &den + &den * d[(0, 0)];
+    Ok((num, den))
+}
+
+/// Format a polynomial. `monomial(i)` is the power of the variable that
+/// belongs to coefficient i, or `None` for the constant term.
+fn format_poly(
+    vals: ArrayView1<'_, f64>,
+    monomial: impl Fn(usize) -> Option<String>,
+) -> Result<String, fmt::Error> {
+    let mut out = String::new();
+    for (i, el) in vals.iter().enumerate() {
+        if *el == 0.0 {
+            continue;
+        }
+        let monomial = monomial(i);
+        let show_coefficient = el.abs() != 1.0 || monomial.is_none();
+        if out.is_empty() {
+            if *el == -1.0 && !show_coefficient {
+                write!(out, "-")?;
+            } else if show_coefficient {
+                write!(out, "{}", NiceFloat(*el))?;
             }
-            if !written {
-                write!(out, "0")?;
+        } else {
+            let sign = if *el < 0. { '-' } else { '+' };
+            write!(out, " {sign}")?;
+            if show_coefficient {
+                write!(out, " {}", NiceFloat(el.abs()))?;
             }
-            Ok(out)
         }
-        let num = format_poly(self.num.view())?;
-        let den_is_one = self.den[0] == 1.0 && self.den.iter().skip(1).all(|e| *e == 0.0);
-        let den = format_poly(self.den.view())?;
-        let mut len = num.len();
-        if !den_is_one {
-            len = len.max(den.len())
-        };
-        writeln!(f, "{}{}", " ".repeat((len - num.len()) / 2), num)?;
-        if !den_is_one {
-            writeln!(f, "{}", "-".repeat(len))?;
-            writeln!(f, "{}{}", " ".repeat((len - den.len()) / 2), den)?;
+        if let Some(monomial) = monomial {
+            if !out.is_empty() && out != "-" {
+                write!(out, " ")?;
+            }
+            write!(out, "{monomial}")?;
         }
-        Ok(())
     }
+    if out.is_empty() {
+        write!(out, "0")?;
+    }
+    Ok(out)
+}
+
+/// Write `num` over `den` with a fraction bar, both centered
+pub(crate) fn format_fraction(
+    f: &mut fmt::Formatter<'_>,
+    num: &str,
+    den: Option<&String>,
+) -> fmt::Result {
+    let mut len = num.len();
+    if let Some(den) = den {
+        len = len.max(den.len())
+    };
+    writeln!(f, "{}{}", " ".repeat((len - num.len()) / 2), num)?;
+    if let Some(den) = den {
+        writeln!(f, "{}", "-".repeat(len))?;
+        writeln!(f, "{}{}", " ".repeat((len - den.len()) / 2), den)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -134,6 +323,7 @@ mod tests {
         let tf = DiscreteTransferFunction {
             num: array![-1.0, 1.5, -2.0],
             den: array![1.5, 0.5, 0.75],
+            sample_time: None,
         };
         let out = format!("{tf}");
         assert_eq!(
@@ -142,14 +332,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn continuous_tf_display() {
+        let tf =
+            ContinuousTransferFunction::new(array![0.0, 1.0, -2.0], array![2.0, 1.0, 0.5, 1.0])
+                .unwrap();
+        let out = format!("{tf}");
+        assert_eq!(
+            &out,
+            "         s - 2\n-----------------------\n2 s^3 + s^2 + 0.5 s + 1\n"
+        );
+        let tf = ContinuousTransferFunction::new(array![-1.0, 0.0, 0.0], array![1.0, -1.0, 2.0])
+            .unwrap();
+        assert_eq!(&format!("{tf}"), "   -s^2\n-----------\ns^2 - s + 2\n");
+        let tf = ContinuousTransferFunction::new(array![-1.0, 0.0], array![1.0]);
+        assert!(matches!(tf, Err(Error::InvalidModel(_))));
+        let tf = ContinuousTransferFunction::new(array![3.0], array![0.0, 1.0]).unwrap();
+        assert_eq!(&format!("{tf}"), "3\n");
+    }
+
+    #[test]
+    fn continuous_state_space_conversion() {
+        let tf = ContinuousTransferFunction::new(array![1.0, 3.0], array![2.0, 6.0, 4.0]).unwrap();
+        let ss = tf.convert_to_state_space().unwrap();
+        assert_relative_eq!(ss.a(), array![[-3.0, -2.0], [1.0, 0.0]]);
+        assert_relative_eq!(ss.b(), array![[1.0], [0.0]]);
+        assert_relative_eq!(ss.c(), array![[0.5, 1.5]]);
+        assert_relative_eq!(ss.d(), array![[0.0]]);
+    }
+
+    #[test]
+    fn state_space_round_trip() {
+        let tf = ContinuousTransferFunction::new(array![1.0, 3.0], array![2.0, 6.0, 4.0]).unwrap();
+        let ss = tf.convert_to_state_space().unwrap();
+        let back = ContinuousTransferFunction::from_state_space(&ss).unwrap();
+        assert_relative_eq!(back.num(), array![0.0, 0.5, 1.5]);
+        assert_relative_eq!(back.den(), array![1.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn invalid_tf() {
+        assert!(matches!(
+            DiscreteTransferFunction::new(array![], array![1.0]),
+            Err(Error::InvalidModel(_))
+        ));
+        assert!(matches!(
+            DiscreteTransferFunction::new(array![1.0], array![0.0, 1.0]),
+            Err(Error::InvalidModel(_))
+        ));
+    }
+
     #[test]
     fn state_space_conversion() {
         let tf = DiscreteTransferFunction {
             num: array![1.0, 1.5, 2.0],
             den: array![1.5, 0.5, 0.75],
+            sample_time: None,
         };
         let ss = tf.convert_to_state_space().unwrap();
-        assert_relative_eq!(ss.a(), array![[-1. / 3., 1.], [-0.5, 0.]]);
+        assert_relative_eq!(ss.a(), array![[-1. / 3., -0.5], [1.0, 0.]]);
         assert_relative_eq!(ss.b(), array![[1.0], [0.0]]);
         assert_relative_eq!(ss.c(), array![[7.0 / 9.0, 1.0]]);
         assert_relative_eq!(ss.d(), array![[2.0 / 3.0]]);
@@ -160,6 +401,7 @@ mod tests {
         let tf = DiscreteTransferFunction {
             num: array![2.0],
             den: array![3.0],
+            sample_time: None,
         };
         let ss = tf.convert_to_state_space().unwrap();
         assert_relative_eq!(ss.a(), Array2::zeros((0, 0)));
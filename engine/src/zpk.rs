@@ -0,0 +1,379 @@
+//! Poles, zeros and the zero-pole-gain representation
+
+use nalgebra::Complex;
+use ndarray::prelude::*;
+use std::fmt;
+
+use crate::error::Error;
+use crate::linalg;
+use crate::state_space::{check_sample_time, ContinuousStateSpaceModel, DiscreteStateSpaceModel};
+use crate::transfer_function::{
+    format_fraction, ContinuousTransferFunction, DiscreteTransferFunction,
+};
+use crate::{NiceComplex, NiceFloat};
+
+/// Discrete Time Transfer Function in zero-pole-gain form
+///
+/// H(z) = gain * prod(z - zeros[i]) / prod(z - poles[j])
+///
+/// Invariant: complex zeros and poles come in conjugate pairs and
+/// there are not more zeros than poles
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zpk {
+    zeros: Vec<Complex<f64>>,
+    poles: Vec<Complex<f64>>,
+    gain: f64,
+    /// time between two steps in seconds, `None` if not specified
+    sample_time: Option<f64>,
+}
+
+impl Zpk {
+    pub fn new(
+        zeros: Vec<Complex<f64>>,
+        poles: Vec<Complex<f64>>,
+        gain: f64,
+    ) -> Result<Self, Error> {
+        if zeros.len() > poles.len() {
+            return Err(Error::InvalidModel(
+                format!(
+                    "{} zeros and {} poles, but a causal model has at least as many poles as zeros",
+                    zeros.len(),
+                    poles.len()
+                )
+                .into(),
+            ));
+        }
+        if !has_conjugate_pairs(&zeros) || !has_conjugate_pairs(&poles) {
+            return Err(Error::InvalidModel(
+                "complex zeros and poles must come in conjugate pairs".into(),
+            ));
+        }
+        if !gain.is_finite() {
+            return Err(Error::InvalidModel("gain must be finite".into()));
+        }
+        // a model without gain has no zeros
+        let zeros = if gain == 0.0 { Vec::new() } else { zeros };
+        Ok(Self {
+            zeros,
+            poles,
+            gain,
+            sample_time: None,
+        })
+    }
+
+    pub fn with_sample_time(mut self, ts: f64) -> Result<Self, Error> {
+        check_sample_time(ts)?;
+        self.sample_time = Some(ts);
+        Ok(self)
+    }
+
+    pub fn sample_time(&self) -> Option<f64> {
+        self.sample_time
+    }
+
+    pub fn zeros(&self) -> &[Complex<f64>] {
+        &self.zeros
+    }
+    pub fn poles(&self) -> &[Complex<f64>] {
+        &self.poles
+    }
+    pub fn gain(&self) -> f64 {
+        self.gain
+    }
+
+    pub fn dc_gain(&self) -> f64 {
+        let one = Complex::new(1.0, 0.0);
+        let num: Complex<f64> = self.zeros.iter().map(|z| one - z).product();
+        let den: Complex<f64> = self.poles.iter().map(|p| one - p).product();
+        (num * self.gain / den).re
+    }
+
+    pub fn from_transfer_function(tf: &DiscreteTransferFunction) -> Self {
+        let (num, den) = z_polynomials(tf);
+        let leading = num.iter().copied().find(|e| *e != 0.0).unwrap_or(0.0);
+        Self {
+            zeros: tf.zeros(),
+            poles: linalg::roots(den),
+            gain: leading / den[0],
+            sample_time: tf.sample_time(),
+        }
+    }
+
+    /// Zero-pole-gain form of a single input single output model
+    pub fn from_state_space(ss: &DiscreteStateSpaceModel) -> Result<Self, Error> {
+        Ok(Self::from_transfer_function(
+            &DiscreteTransferFunction::from_state_space(ss)?,
+        ))
+    }
+
+    pub fn to_transfer_function(&self) -> Result<DiscreteTransferFunction, Error> {
+        let den = linalg::poly_from_roots(&self.poles);
+        // missing zeros are delays
+        let mut num = Array1::zeros(den.len());
+        num.slice_mut(s![self.poles.len() - self.zeros.len()..])
+            .assign(&(linalg::poly_from_roots(&self.zeros) * self.gain));
+        let tf = DiscreteTransferFunction::new(num, den)?;
+        match self.sample_time {
+            Some(ts) => tf.with_sample_time(ts),
+            None => Ok(tf),
+        }
+    }
+
+    pub fn to_state_space(&self) -> Result<DiscreteStateSpaceModel, Error> {
+        self.to_transfer_function()?.convert_to_state_space()
+    }
+}
+
+impl fmt::Display for Zpk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let factors = |roots: &[Complex<f64>]| {
+            roots
+                .iter()
+                .map(|r| format_factor(*r))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let num = match (self.gain, self.zeros.is_empty()) {
+            (gain, true) => NiceFloat(gain).to_string(),
+            (1.0, false) => factors(&self.zeros),
+            (-1.0, false) => format!("-{}", factors(&self.zeros)),
+            (gain, false) => format!("{} {}", NiceFloat(gain), factors(&self.zeros)),
+        };
+        let den = factors(&self.poles);
+        format_fraction(f, &num, (!self.poles.is_empty()).then_some(&den))?;
+        if let Some(ts) = self.sample_time {
+            writeln!(f, "Ts = {}", NiceFloat(ts))?;
+        }
+        Ok(())
+    }
+}
+
+impl DiscreteTransferFunction {
+    pub fn poles(&self) -> Vec<Complex<f64>> {
+        linalg::roots(z_polynomials(self).1)
+    }
+
+    pub fn zeros(&self) -> Vec<Complex<f64>> {
+        linalg::roots(z_polynomials(self).0)
+    }
+
+    /// Gain at z = 1, infinite for poles at z = 1
+    pub fn dc_gain(&self) -> f64 {
+        self.num().sum() / self.den().sum()
+    }
+}
+
+impl DiscreteStateSpaceModel {
+    pub fn poles(&self) -> Vec<Complex<f64>> {
+        linalg::eigenvalues(self.a())
+    }
+
+    /// Zeros of a single input single output model
+    pub fn zeros(&self) -> Result<Vec<Complex<f64>>, Error> {
+        Ok(DiscreteTransferFunction::from_state_space(self)?.zeros())
+    }
+
+    /// Gain from each input to each output at z = 1,
+    /// infinite for poles at z = 1
+    pub fn dc_gain(&self) -> Array2<f64> {
+        let n = self.state_size();
+        let shifted = Array2::<f64>::eye(n) - self.a();
+        state_space_dc_gain(shifted.view(), self.b(), self.c(), self.d())
+    }
+}
+
+impl ContinuousTransferFunction {
+    pub fn poles(&self) -> Vec<Complex<f64>> {
+        linalg::roots(self.den())
+    }
+
+    pub fn zeros(&self) -> Vec<Complex<f64>> {
+        linalg::roots(self.num())
+    }
+
+    /// Gain at s = 0, infinite for poles at s = 0
+    pub fn dc_gain(&self) -> f64 {
+        let last = self.den().len() - 1;
+        self.num()[last] / self.den()[last]
+    }
+}
+
+impl ContinuousStateSpaceModel {
+    pub fn poles(&self) -> Vec<Complex<f64>> {
+        linalg::eigenvalues(self.a())
+    }
+
+    /// Zeros of a single input single output model
+    pub fn zeros(&self) -> Result<Vec<Complex<f64>>, Error> {
+        Ok(ContinuousTransferFunction::from_state_space(self)?.zeros())
+    }
+
+    /// Gain from each input to each output at s = 0,
+    /// infinite for poles at s = 0
+    pub fn dc_gain(&self) -> Array2<f64> {
+        let negated = -&self.a();
+        state_space_dc_gain(negated.view(), self.b(), self.c(), self.d())
+    }
+}
+
+/// Numerator and denominator of a transfer function in powers of z,
+/// highest power first, without common factors z
+pub(crate) fn z_polynomials(
+    tf: &DiscreteTransferFunction,
+) -> (ArrayView1<'_, f64>, ArrayView1<'_, f64>) {
+    let (num, den) = (tf.num(), tf.den());
+    let common = num
+        .iter()
+        .zip(den.iter())
+        .rev()
+        .take_while(|(n, d)| **n == 0.0 && **d == 0.0)
+        .count();
+    let len = num.len() - common;
+    (num.slice_move(s![..len]), den.slice_move(s![..len]))
+}
+
+/// c m^-1 b + d
+fn state_space_dc_gain(
+    m: ArrayView2<'_, f64>,
+    b: ArrayView2<'_, f64>,
+    c: ArrayView2<'_, f64>,
+    d: ArrayView2<'_, f64>,
+) -> Array2<f64> {
+    match linalg::inverse(m) {
+        Some(inv) => c.dot(&inv).dot(&b) + d,
+        None => Array2::from_elem(d.raw_dim(), f64::INFINITY),
+    }
+}
+
+/// Whether every complex root has a conjugate of the same multiplicity
+fn has_conjugate_pairs(roots: &[Complex<f64>]) -> bool {
+    let mut unmatched: Vec<_> = roots.iter().filter(|r| r.im != 0.0).collect();
+    while let Some(root) = unmatched.pop() {
+        let tolerance = 1e-9 * root.norm();
+        match unmatched
+            .iter()
+            .position(|other| (*other - root.conj()).norm() <= tolerance)
+        {
+            Some(i) => {
+                unmatched.swap_remove(i);
+            }
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Factor (z - root) of a polynomial
+fn format_factor(root: Complex<f64>) -> String {
+    if root == Complex::new(0.0, 0.0) {
+        "z".to_string()
+    } else if root.im != 0.0 {
+        format!("(z - ({}))", NiceComplex(root))
+    } else if root.re < 0.0 {
+        format!("(z + {})", NiceFloat(-root.re))
+    } else {
+        format!("(z - {})", NiceFloat(root.re))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn sorted(mut roots: Vec<Complex<f64>>) -> Vec<Complex<f64>> {
+        roots.sort_by(|a, b| a.re.total_cmp(&b.re).then(a.im.total_cmp(&b.im)));
+        roots
+    }
+
+    #[test]
+    fn poles_zeros_and_dc_gain() {
+        // (z^-1 + 0.5 z^-2) / (1 - 1.5 z^-1 + 0.7 z^-2)
+        let tf =
+            DiscreteTransferFunction::new(array![0.0, 1.0, 0.5], array![1.0, -1.5, 0.7]).unwrap();
+        let poles = sorted(tf.poles());
+        let im = (0.7 - 0.75f64.powi(2)).sqrt();
+        assert_relative_eq!(poles[0].re, 0.75, epsilon = 1e-12);
+        assert_relative_eq!(poles[0].im, -im, epsilon = 1e-12);
+        assert_relative_eq!(poles[1].im, im, epsilon = 1e-12);
+        assert_eq!(tf.zeros(), vec![Complex::new(-0.5, 0.0)]);
+        assert_relative_eq!(tf.dc_gain(), 1.5 / 0.2, epsilon = 1e-12);
+
+        let ss = tf.convert_to_state_space().unwrap();
+        let ss_poles = sorted(ss.poles());
+        for (a, b) in ss_poles.iter().zip(&poles) {
+            assert_relative_eq!((a - b).norm(), 0.0, epsilon = 1e-12);
+        }
+        assert_relative_eq!(ss.zeros().unwrap()[0].re, -0.5, epsilon = 1e-12);
+        assert_relative_eq!(ss.dc_gain(), array![[7.5]], epsilon = 1e-12);
+
+        // common factors z are not poles and zeros
+        let tf = DiscreteTransferFunction::new(array![1.0, 0.0], array![1.0, 0.0]).unwrap();
+        assert!(tf.poles().is_empty() && tf.zeros().is_empty());
+    }
+
+    #[test]
+    fn continuous_dc_gain() {
+        let tf = ContinuousTransferFunction::new(array![2.0], array![1.0, 4.0]).unwrap();
+        assert_eq!(tf.poles(), vec![Complex::new(-4.0, 0.0)]);
+        assert_eq!(tf.dc_gain(), 0.5);
+        let ss = tf.convert_to_state_space().unwrap();
+        assert_relative_eq!(ss.dc_gain(), array![[0.5]], epsilon = 1e-12);
+        let integrator = ContinuousTransferFunction::new(array![1.0], array![1.0, 0.0]).unwrap();
+        let ss = integrator.convert_to_state_space().unwrap();
+        assert_eq!(ss.dc_gain(), array![[f64::INFINITY]]);
+    }
+
+    #[test]
+    fn zpk_round_trip() {
+        let tf = DiscreteTransferFunction::new(array![0.0, 2.0, 1.0], array![1.0, -1.5, 0.7])
+            .unwrap()
+            .with_sample_time(0.1)
+            .unwrap();
+        let zpk = Zpk::from_transfer_function(&tf);
+        assert_eq!(zpk.gain(), 2.0);
+        assert_eq!(zpk.zeros(), &[Complex::new(-0.5, 0.0)]);
+        assert_relative_eq!(zpk.dc_gain(), tf.dc_gain(), epsilon = 1e-12);
+        let back = zpk.to_transfer_function().unwrap();
+        assert_eq!(back.sample_time(), Some(0.1));
+        assert_relative_eq!(back.num(), tf.num(), epsilon = 1e-12);
+        assert_relative_eq!(back.den(), tf.den(), epsilon = 1e-12);
+        let ss = zpk.to_state_space().unwrap();
+        let again = Zpk::from_state_space(&ss).unwrap();
+        assert_relative_eq!(again.gain(), 2.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn zpk_display() {
+        let zpk = Zpk::new(
+            vec![Complex::new(0.0, 0.0)],
+            vec![Complex::new(1.0, 0.0), Complex::new(-0.5, 0.0)],
+            0.5,
+        )
+        .unwrap()
+        .with_sample_time(0.01)
+        .unwrap();
+        assert_eq!(
+            zpk.to_string(),
+            "      0.5 z\n-----------------\n(z - 1) (z + 0.5)\nTs = 0.01\n"
+        );
+        let zpk = Zpk::new(
+            vec![],
+            vec![Complex::new(0.5, 0.5), Complex::new(0.5, -0.5)],
+            1.0,
+        )
+        .unwrap();
+        assert_eq!(
+            zpk.to_string(),
+            "                  1\n-------------------------------------\n(z - (0.5 + 0.5i)) (z - (0.5 - 0.5i))\n"
+        );
+    }
+
+    #[test]
+    fn invalid_zpk() {
+        let p = Complex::new(0.5, 0.5);
+        assert!(Zpk::new(vec![], vec![p], 1.0).is_err());
+        assert!(Zpk::new(vec![p, p.conj(), p], vec![p, p.conj()], 1.0).is_err());
+        assert!(Zpk::new(vec![], vec![p, p.conj()], f64::NAN).is_err());
+    }
+}
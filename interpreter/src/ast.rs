@@ -16,14 +16,27 @@ pub(crate) enum Expression {
     Identifier(Rc<str>),
     StringLiteral(Rc<str>),
     FloatLiteral(f64),
+    /// imaginary number, the value is the imaginary part
+    ImaginaryLiteral(f64),
     VectorLiteral(Vec<Expression>),
+    /// rows separated by `;`
+    MatrixLiteral(Vec<Vec<Expression>>),
     UnOp(UnOp, Box<Expression>),
     BinOp(BinOp, Box<Expression>, Box<Expression>),
     FunctionCall {
         function: Box<Expression>,
         arguments: Vec<Expression>,
     },
-    System(Vec<SystemItem>),
+    System(SystemDef),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SystemDef {
+    /// names of the external inputs, empty if not declared
+    pub inputs: Vec<Rc<str>>,
+    pub items: Vec<SystemItem>,
+    /// names of the output signals, empty if not declared
+    pub outputs: Vec<Rc<str>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -34,13 +47,16 @@ pub(crate) struct SystemItem {
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum SystemItemRhs {
-    Difference {
-        input1_name: Rc<str>,
-        input2_name: Rc<str>,
+    /// weighted sum, (gain, input name) per term
+    Sum(Vec<(f64, Rc<str>)>),
+    /// static gain, either a scalar or a matrix
+    Gain {
+        gain: Expression,
+        input_name: Rc<str>,
     },
     System {
         system_name: Rc<str>,
-        input_name: Rc<str>,
+        input_names: Vec<Rc<str>>,
     },
 }
 
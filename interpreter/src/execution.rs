@@ -1,12 +1,21 @@
-use ndarray::{Array1, Array2, Axis};
+use ndarray::{array, Array1, Array2, Array3, Axis};
 use std::collections::HashMap;
 use std::rc::Rc;
 
+use engine::discretization::Method;
 use engine::dynamic_system::{
     CompoundSystem, CompoundSystemComponentDefinition, Simulation, SystemBlock,
 };
-use engine::state_space::DiscreteStateSpaceModel;
-use engine::transfer_function::DiscreteTransferFunction;
+use engine::frequency_response::{
+    frequency_grid, nyquist_stability, stability_margins, unwrapped_phase, NyquistStability,
+    StabilityMargins,
+};
+use engine::ode::Solver;
+use engine::root_locus::RootLocus;
+use engine::state_space::{ContinuousStateSpaceModel, DiscreteStateSpaceModel};
+use engine::transfer_function::{ContinuousTransferFunction, DiscreteTransferFunction};
+use engine::zpk::Zpk;
+use engine::{Complex, NiceComplex, NiceFloat};
 
 use crate::ast::{self, SystemItemRhs};
 use ast::{Expression, Program, Statement};
@@ -14,10 +23,12 @@ use ast::{Expression, Program, Statement};
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     IO(std::fmt::Error),
+    Engine(engine::error::Error),
     NullDeref(Rc<str>),
     UnknownFunction(Rc<str>),
     TypeError,
     IncorrectNumberOfArguments(usize, usize),
+    ArgumentCountOutOfRange(usize, usize, usize),
     Other(Rc<str>),
 }
 
@@ -27,16 +38,80 @@ impl From<std::fmt::Error> for Error {
     }
 }
 
+impl From<engine::error::Error> for Error {
+    fn from(value: engine::error::Error) -> Self {
+        Self::Engine(value)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IO(e) => write!(f, "{e}"),
+            Error::Engine(e) => write!(f, "{e}"),
+            Error::NullDeref(id) => write!(f, "{id} is not defined"),
+            Error::UnknownFunction(id) => write!(f, "unknown function {id}"),
+            Error::TypeError => write!(f, "type error"),
+            Error::IncorrectNumberOfArguments(expected, got) => {
+                write!(f, "expected {expected} arguments, but got {got}")
+            }
+            Error::ArgumentCountOutOfRange(min, max, got) => {
+                write!(f, "expected {min} to {max} arguments, but got {got}")
+            }
+            Error::Other(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
 /// Runtime Value
 #[derive(Clone, Debug, PartialEq)]
 enum Value {
     String(Rc<str>),
     Float(f64),
+    Complex(Complex<f64>),
     Vector(Rc<Array1<f64>>),
     Matrix(Rc<Array2<f64>>),
+    ComplexVector(Rc<Array1<Complex<f64>>>),
+    /// signals over time, one row per signal
+    TimeSeries {
+        time: Rc<Array1<f64>>,
+        data: Rc<Array2<f64>>,
+    },
+    /// magnitude in dB and phase in degrees over the frequency in rad/s,
+    /// one row per input output pair
+    FrequencyResponse {
+        frequency: Rc<Array1<f64>>,
+        magnitude: Rc<Array2<f64>>,
+        phase: Rc<Array2<f64>>,
+        /// of single input single output models
+        margins: Option<StabilityMargins>,
+    },
+    Margins(StabilityMargins),
+    /// in the z-plane for discrete models
+    PoleZeroMap {
+        poles: Rc<Array1<Complex<f64>>>,
+        zeros: Rc<Array1<Complex<f64>>>,
+        discrete: bool,
+    },
+    /// closed loop poles of an open loop under proportional feedback
+    RootLocus {
+        locus: Rc<RootLocus>,
+        poles: Rc<Array1<Complex<f64>>>,
+        zeros: Rc<Array1<Complex<f64>>>,
+        discrete: bool,
+    },
+    /// open loop response from negative to positive frequencies
+    NyquistCurve {
+        real: Rc<Array1<f64>>,
+        imag: Rc<Array1<f64>>,
+        stability: NyquistStability,
+    },
     BuiltInFunction(BuiltInFunction),
     TransferFunction(Rc<DiscreteTransferFunction>),
     StateSpaceModel(Rc<DiscreteStateSpaceModel>),
+    ContinuousTransferFunction(Rc<ContinuousTransferFunction>),
+    ContinuousStateSpaceModel(Rc<ContinuousStateSpaceModel>),
+    Zpk(Rc<Zpk>),
     CompoundSystem(Rc<CompoundSystem>),
 }
 
@@ -45,6 +120,38 @@ pub enum Output {
     Err(Error),
     Text(Rc<str>),
     Plot(Rc<Array2<f64>>),
+    /// one row per signal, plotted over the time in seconds
+    TimePlot {
+        time: Rc<Array1<f64>>,
+        data: Rc<Array2<f64>>,
+    },
+    /// magnitude in dB and phase in degrees over the frequency in rad/s
+    BodePlot {
+        frequency: Rc<Array1<f64>>,
+        magnitude: Rc<Array2<f64>>,
+        phase: Rc<Array2<f64>>,
+        /// to be marked in the plot
+        margins: Option<StabilityMargins>,
+    },
+    /// poles and zeros in the s-plane, or the z-plane for discrete models
+    PoleZeroMap {
+        poles: Rc<Array1<Complex<f64>>>,
+        zeros: Rc<Array1<Complex<f64>>>,
+        discrete: bool,
+    },
+    /// branches of the closed loop poles over the open loop poles and zeros
+    RootLocusPlot {
+        locus: Rc<RootLocus>,
+        poles: Rc<Array1<Complex<f64>>>,
+        zeros: Rc<Array1<Complex<f64>>>,
+        discrete: bool,
+    },
+    /// open loop response in the complex plane with the stability verdict
+    NyquistPlot {
+        real: Rc<Array1<f64>>,
+        imag: Rc<Array1<f64>>,
+        stability: NyquistStability,
+    },
     System(Rc<CompoundSystem>),
 }
 
@@ -52,33 +159,371 @@ impl From<&Value> for Output {
     fn from(value: &Value) -> Self {
         match value {
             Value::String(s) => Output::Text(s.clone()),
-            Value::Vector(data) => Output::Text(data.to_string().into()),
+            Value::Vector(data) => {
+                let elements: Vec<_> = data.iter().map(|f| NiceFloat(*f).to_string()).collect();
+                Output::Text(format!("[{}]", elements.join(", ")).into())
+            }
             Value::Matrix(data) => Output::Plot(data.clone()),
+            Value::ComplexVector(data) => {
+                let elements: Vec<_> = data.iter().map(|c| NiceComplex(*c).to_string()).collect();
+                Output::Text(format!("[{}]", elements.join(", ")).into())
+            }
+            Value::TimeSeries { time, data } => Output::TimePlot {
+                time: time.clone(),
+                data: data.clone(),
+            },
+            Value::FrequencyResponse {
+                frequency,
+                magnitude,
+                phase,
+                margins,
+            } => Output::BodePlot {
+                frequency: frequency.clone(),
+                magnitude: magnitude.clone(),
+                phase: phase.clone(),
+                margins: *margins,
+            },
+            Value::Margins(margins) => Output::Text(margins.to_string().into()),
+            Value::PoleZeroMap {
+                poles,
+                zeros,
+                discrete,
+            } => Output::PoleZeroMap {
+                poles: poles.clone(),
+                zeros: zeros.clone(),
+                discrete: *discrete,
+            },
+            Value::RootLocus {
+                locus,
+                poles,
+                zeros,
+                discrete,
+            } => Output::RootLocusPlot {
+                locus: locus.clone(),
+                poles: poles.clone(),
+                zeros: zeros.clone(),
+                discrete: *discrete,
+            },
+            Value::NyquistCurve {
+                real,
+                imag,
+                stability,
+            } => Output::NyquistPlot {
+                real: real.clone(),
+                imag: imag.clone(),
+                stability: *stability,
+            },
             Value::Float(f) => Output::Text(f.to_string().into()),
+            Value::Complex(c) => Output::Text(NiceComplex(*c).to_string().into()),
             Value::BuiltInFunction(_) => Output::Text("<builtin_function>".to_string().into()),
             Value::TransferFunction(tf) => Output::Text(tf.to_string().into()),
             Value::StateSpaceModel(ss) => Output::Text(ss.to_string().into()),
+            Value::ContinuousTransferFunction(tf) => Output::Text(tf.to_string().into()),
+            Value::ContinuousStateSpaceModel(ss) => Output::Text(ss.to_string().into()),
+            Value::Zpk(zpk) => Output::Text(zpk.to_string().into()),
             Value::CompoundSystem(s) => Output::System(s.clone()),
         }
     }
 }
 
+/// Transfer function evaluated at a point of the s-plane or z-plane
+type PointResponse<'a> = Box<dyn Fn(Complex<f64>) -> Complex<f64> + 'a>;
+
 impl Value {
     fn get_system(&self) -> Result<SystemBlock, Error> {
         match self {
             Value::TransferFunction(tf) => Ok(SystemBlock::TransferFunction(tf.clone())),
             Value::StateSpaceModel(ss) => Ok(SystemBlock::StateSpace(ss.clone())),
+            Value::ContinuousTransferFunction(tf) => {
+                Ok(SystemBlock::ContinuousTransferFunction(tf.clone()))
+            }
+            Value::ContinuousStateSpaceModel(ss) => {
+                Ok(SystemBlock::ContinuousStateSpace(ss.clone()))
+            }
+            Value::Zpk(zpk) => Ok(SystemBlock::TransferFunction(Rc::new(
+                zpk.to_transfer_function()?,
+            ))),
+            Value::CompoundSystem(sys) => Ok(SystemBlock::SubSystem(sys.clone())),
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    /// Elements of a real or complex scalar or vector,
+    /// and whether the value is a scalar
+    fn get_complex_elements(&self) -> Result<(Array1<Complex<f64>>, bool), Error> {
+        match self {
+            Value::Float(f) => Ok((Array1::from_elem(1, Complex::new(*f, 0.0)), true)),
+            Value::Complex(c) => Ok((Array1::from_elem(1, *c), true)),
+            Value::Vector(v) => Ok((v.mapv(|f| Complex::new(f, 0.0)), false)),
+            Value::ComplexVector(v) => Ok(((**v).clone(), false)),
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    /// Response at the frequencies `w`, one row per input output pair
+    fn get_frequency_response(&self, w: &Array1<f64>) -> Result<Array2<Complex<f64>>, Error> {
+        let pairs = |response: Array3<Complex<f64>>| {
+            let (n, outputs, inputs) = response.dim();
+            response
+                .into_shape_with_order((n, outputs * inputs))
+                .unwrap()
+                .reversed_axes()
+        };
+        let row = |response: Array1<Complex<f64>>| response.insert_axis(Axis(0));
+        match self {
+            Value::TransferFunction(tf) => Ok(row(tf.frequency_response(w.view()))),
+            Value::StateSpaceModel(ss) => Ok(pairs(ss.frequency_response(w.view()))),
+            Value::ContinuousTransferFunction(tf) => Ok(row(tf.frequency_response(w.view()))),
+            Value::ContinuousStateSpaceModel(ss) => Ok(pairs(ss.frequency_response(w.view()))),
+            Value::Zpk(zpk) => Ok(row(zpk
+                .to_transfer_function()?
+                .frequency_response(w.view()))),
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    /// Frequencies that cover the poles and zeros of a model
+    fn get_frequency_grid(&self) -> Result<Array1<f64>, Error> {
+        let (roots, sample_time) = self.get_roots_and_sample_time()?;
+        Ok(frequency_grid(&roots, sample_time))
+    }
+
+    /// Stability margins of a single input single output open loop model
+    fn get_stability_margins(&self) -> Result<StabilityMargins, Error> {
+        let (roots, sample_time) = self.get_roots_and_sample_time()?;
+        let transfer_function = self.get_siso_transfer_function()?;
+        let response = |w: f64| match sample_time {
+            Some(ts) => transfer_function(Complex::from_polar(1.0, w * ts)),
+            None => transfer_function(Complex::new(0.0, w)),
+        };
+        Ok(stability_margins(response, &roots, sample_time))
+    }
+
+    /// Transfer function of a single input single output model,
+    /// evaluated in the s-plane or z-plane
+    fn get_siso_transfer_function(&self) -> Result<PointResponse<'_>, Error> {
+        let check = |(outputs, inputs)| {
+            if (outputs, inputs) == (1, 1) {
+                Ok(())
+            } else {
+                Err(Error::Other(
+                    "expected a model with a single input and output".into(),
+                ))
+            }
+        };
+        match self {
+            Value::TransferFunction(tf) => Ok(Box::new(|z| tf.response_at(z))),
+            Value::StateSpaceModel(ss) => {
+                check(ss.d().dim())?;
+                Ok(Box::new(|z| ss.response_at(z)[(0, 0)]))
+            }
+            Value::ContinuousTransferFunction(tf) => Ok(Box::new(|s| tf.response_at(s))),
+            Value::ContinuousStateSpaceModel(ss) => {
+                check(ss.d().dim())?;
+                Ok(Box::new(|s| ss.response_at(s)[(0, 0)]))
+            }
+            Value::Zpk(zpk) => {
+                let tf = zpk.to_transfer_function()?;
+                Ok(Box::new(move |z| tf.response_at(z)))
+            }
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    fn is_model(&self) -> bool {
+        matches!(
+            self,
+            Value::TransferFunction(_)
+                | Value::ContinuousTransferFunction(_)
+                | Value::Zpk(_)
+                | Value::StateSpaceModel(_)
+                | Value::ContinuousStateSpaceModel(_)
+        )
+    }
+
+    /// Transfer functions as state space models, other values unchanged
+    fn to_state_space(&self) -> Result<Value, Error> {
+        Ok(match self {
+            Value::TransferFunction(tf) => {
+                Value::StateSpaceModel(Rc::new(tf.convert_to_state_space()?))
+            }
+            Value::ContinuousTransferFunction(tf) => {
+                Value::ContinuousStateSpaceModel(Rc::new(tf.convert_to_state_space()?))
+            }
+            Value::Zpk(zpk) => Value::StateSpaceModel(Rc::new(zpk.to_state_space()?)),
+            v => v.clone(),
+        })
+    }
+
+    fn get_poles(&self) -> Result<Vec<Complex<f64>>, Error> {
+        match self {
+            Value::TransferFunction(tf) => Ok(tf.poles()),
+            Value::StateSpaceModel(ss) => Ok(ss.poles()),
+            Value::ContinuousTransferFunction(tf) => Ok(tf.poles()),
+            Value::ContinuousStateSpaceModel(ss) => Ok(ss.poles()),
+            Value::Zpk(zpk) => Ok(zpk.poles().to_vec()),
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    fn get_zeros(&self) -> Result<Vec<Complex<f64>>, Error> {
+        match self {
+            Value::TransferFunction(tf) => Ok(tf.zeros()),
+            Value::StateSpaceModel(ss) => Ok(ss.zeros()?),
+            Value::ContinuousTransferFunction(tf) => Ok(tf.zeros()),
+            Value::ContinuousStateSpaceModel(ss) => Ok(ss.zeros()?),
+            Value::Zpk(zpk) => Ok(zpk.zeros().to_vec()),
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    /// Poles and zeros of a model and the sample time of discrete models
+    fn get_roots_and_sample_time(&self) -> Result<(Vec<Complex<f64>>, Option<f64>), Error> {
+        let (mut roots, zeros, sample_time) = match self {
+            Value::TransferFunction(tf) => (tf.poles(), Ok(tf.zeros()), tf.sample_time()),
+            Value::StateSpaceModel(ss) => (ss.poles(), ss.zeros(), ss.sample_time()),
+            Value::ContinuousTransferFunction(tf) => (tf.poles(), Ok(tf.zeros()), None),
+            Value::ContinuousStateSpaceModel(ss) => (ss.poles(), ss.zeros(), None),
+            Value::Zpk(zpk) => (
+                zpk.poles().to_vec(),
+                Ok(zpk.zeros().to_vec()),
+                zpk.sample_time(),
+            ),
+            _ => return Err(Error::TypeError),
+        };
+        // zeros are only available for single input single output models
+        roots.extend(zeros.unwrap_or_default());
+        // discrete models without sample time run at one step per second
+        let discrete = !matches!(
+            self,
+            Value::ContinuousTransferFunction(_) | Value::ContinuousStateSpaceModel(_)
+        );
+        let sample_time = sample_time.or(discrete.then_some(1.0));
+        Ok((roots, sample_time))
+    }
+
+    /// Roots from a real or complex vector or a scalar
+    fn get_roots(&self) -> Result<Vec<Complex<f64>>, Error> {
+        match self {
+            Value::Float(f) => Ok(vec![Complex::new(*f, 0.0)]),
+            Value::Vector(v) => Ok(v.iter().map(|f| Complex::new(*f, 0.0)).collect()),
+            Value::ComplexVector(v) => Ok(v.to_vec()),
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    /// Matrix from a matrix, a row vector or a scalar
+    fn get_matrix(&self) -> Result<Array2<f64>, Error> {
+        match self {
+            Value::Float(f) => Ok(Array2::from_elem((1, 1), *f)),
+            Value::Vector(v) => Ok(v.to_shape((1, v.len())).unwrap().to_owned()),
+            Value::Matrix(m) => Ok((**m).clone()),
+            _ => Err(Error::TypeError),
+        }
+    }
+
+    /// Input signal with one row per time step, from a vector or a matrix
+    /// with one signal per row
+    fn get_input_signal(&self) -> Result<Array2<f64>, Error> {
+        match self {
+            Value::Vector(v) => Ok(v.to_shape((v.len(), 1)).unwrap().to_owned()),
+            Value::Matrix(m) | Value::TimeSeries { data: m, .. } => Ok(m.t().to_owned()),
             _ => Err(Error::TypeError),
         }
     }
+
+    /// Any system value, wrapped into a `CompoundSystem` if necessary
+    fn get_compound_system(&self) -> Result<Rc<CompoundSystem>, Error> {
+        match self {
+            Value::CompoundSystem(s) => Ok(s.clone()),
+            other => {
+                let block = other.get_system()?;
+                Ok(CompoundSystem::new(
+                    vec![CompoundSystemComponentDefinition {
+                        block,
+                        name: "".into(),
+                        reads_input_from: ["u".into()].into(),
+                    }],
+                    &[],
+                    &[],
+                )?
+                .into())
+            }
+        }
+    }
+
+    /// Any system value as one state space model with its feedback loops
+    /// closed. The model is continuous if the system has continuous states.
+    fn linearize(&self) -> Result<Value, Error> {
+        let system = self.get_compound_system()?;
+        let sim = Simulation::new(&system)?;
+        Ok(if sim.has_continuous_states() {
+            Value::ContinuousStateSpaceModel(Rc::new(sim.linearize_continuous()?))
+        } else {
+            Value::StateSpaceModel(Rc::new(sim.linearize()?))
+        })
+    }
+}
+
+/// Time between two input samples
+fn sim_interval(sim: &Simulation) -> f64 {
+    sim.sample_time().unwrap_or(1.0)
+}
+
+/// Solver for `sim` and `trace`. The explicit solvers take enough steps for
+/// smooth plots, the stiff solver is only limited by its error tolerances.
+fn solver(name: &str, sim: &Simulation) -> Result<Solver, Error> {
+    let (rel_tol, abs_tol, max_step) = (1e-6, 1e-9, sim_interval(sim) / 20.0);
+    match name {
+        "rk4" => Ok(Solver::Rk4 { step: max_step }),
+        "rk45" => Ok(Solver::Rk45 {
+            rel_tol,
+            abs_tol,
+            max_step,
+        }),
+        "stiff" => Ok(Solver::Rosenbrock {
+            rel_tol,
+            abs_tol,
+            max_step: f64::INFINITY,
+        }),
+        _ => Err(Error::Other(
+            format!("unknown solver {name}, expected rk4, rk45 or stiff").into(),
+        )),
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum BuiltInFunction {
     Load,
     TransferFunction,
+    TfS,
+    StateSpace,
     Tf2Ss,
+    Linearize,
+    ZeroPoleGain,
+    Pole,
+    Zero,
+    Abs,
+    Angle,
+    Real,
+    Imag,
+    Conj,
+    Freqresp,
+    Bode,
+    Margin,
+    Nyquist,
+    PzMap,
+    RLocus,
+    Feedback,
+    Series,
+    Parallel,
+    Append,
+    Lft,
+    C2d,
+    D2c,
     Step,
+    Sim,
+    Trace,
 }
 
 pub trait Env {
@@ -90,8 +535,34 @@ fn get_default_values() -> HashMap<Rc<str>, Value> {
     let mut values = HashMap::new();
     values.insert("load".into(), Value::BuiltInFunction(Load));
     values.insert("tf".into(), Value::BuiltInFunction(TransferFunction));
+    values.insert("tf_s".into(), Value::BuiltInFunction(TfS));
+    values.insert("ss".into(), Value::BuiltInFunction(StateSpace));
     values.insert("tf2ss".into(), Value::BuiltInFunction(Tf2Ss));
+    values.insert("linearize".into(), Value::BuiltInFunction(Linearize));
+    values.insert("zpk".into(), Value::BuiltInFunction(ZeroPoleGain));
+    values.insert("pole".into(), Value::BuiltInFunction(Pole));
+    values.insert("zero".into(), Value::BuiltInFunction(Zero));
+    values.insert("abs".into(), Value::BuiltInFunction(Abs));
+    values.insert("angle".into(), Value::BuiltInFunction(Angle));
+    values.insert("real".into(), Value::BuiltInFunction(Real));
+    values.insert("imag".into(), Value::BuiltInFunction(Imag));
+    values.insert("conj".into(), Value::BuiltInFunction(Conj));
+    values.insert("freqresp".into(), Value::BuiltInFunction(Freqresp));
+    values.insert("bode".into(), Value::BuiltInFunction(Bode));
+    values.insert("margin".into(), Value::BuiltInFunction(Margin));
+    values.insert("nyquist".into(), Value::BuiltInFunction(Nyquist));
+    values.insert("pzmap".into(), Value::BuiltInFunction(PzMap));
+    values.insert("rlocus".into(), Value::BuiltInFunction(RLocus));
+    values.insert("feedback".into(), Value::BuiltInFunction(Feedback));
+    values.insert("series".into(), Value::BuiltInFunction(Series));
+    values.insert("parallel".into(), Value::BuiltInFunction(Parallel));
+    values.insert("append".into(), Value::BuiltInFunction(Append));
+    values.insert("lft".into(), Value::BuiltInFunction(Lft));
+    values.insert("c2d".into(), Value::BuiltInFunction(C2d));
+    values.insert("d2c".into(), Value::BuiltInFunction(D2c));
     values.insert("step".into(), Value::BuiltInFunction(Step));
+    values.insert("sim".into(), Value::BuiltInFunction(Sim));
+    values.insert("trace".into(), Value::BuiltInFunction(Trace));
     values
 }
 
@@ -128,40 +599,69 @@ fn eval(
         Identifier(id) => values.get(id).ok_or(Error::NullDeref(id.clone()))?.clone(),
         StringLiteral(s) => Value::String(s.clone()),
         FloatLiteral(f) => Value::Float(*f),
+        ImaginaryLiteral(f) => Value::Complex(Complex::new(0.0, *f)),
         VectorLiteral(elements) => {
             let elements = elements
                 .iter()
-                .map(|e| match eval(e, values, exec_env) {
-                    Ok(Value::Float(f)) => Ok(f),
-                    Ok(_) => Err(Error::TypeError),
-                    Err(e) => Err(e),
-                })
+                .map(|e| eval(e, values, exec_env))
                 .collect::<Result<Vec<_>, _>>()?;
-            Value::Vector(Rc::new(Array1::from_vec(elements)))
+            // the vector is complex if any element is
+            let mut real = Vec::new();
+            let mut complex = Vec::new();
+            for element in &elements {
+                match *element {
+                    Value::Float(f) => real.push(f),
+                    Value::Complex(_) => {}
+                    _ => return Err(Error::TypeError),
+                }
+                complex.push(element.get_complex_elements()?.0[0]);
+            }
+            if real.len() == elements.len() {
+                Value::Vector(Rc::new(Array1::from_vec(real)))
+            } else {
+                Value::ComplexVector(Rc::new(Array1::from_vec(complex)))
+            }
+        }
+        MatrixLiteral(rows) => {
+            let ncols = rows[0].len();
+            let mut m = Array2::zeros((0, ncols));
+            for row in rows {
+                let row = row
+                    .iter()
+                    .map(|e| match eval(e, values, exec_env) {
+                        Ok(Value::Float(f)) => Ok(f),
+                        Ok(_) => Err(Error::TypeError),
+                        Err(e) => Err(e),
+                    })
+                    .collect::<Result<Array1<f64>, _>>()?;
+                m.push(Axis(0), row.view()).map_err(|_| {
+                    Error::Other("all rows must have the same number of values".into())
+                })?;
+            }
+            Value::Matrix(Rc::new(m))
         }
         UnOp(op, e) => {
             use ast::UnOp::*;
-            let Value::Float(f) = eval(e, values, exec_env)? else {
-                return Err(Error::TypeError);
-            };
-            match op {
-                Neg => Value::Float(-f),
+            match (op, eval(e, values, exec_env)?) {
+                (Neg, Value::Float(f)) => Value::Float(-f),
+                (Neg, Value::Complex(c)) => Value::Complex(-c),
+                (Neg, Value::Vector(v)) => Value::Vector(Rc::new(-&*v)),
+                (Neg, Value::ComplexVector(v)) => Value::ComplexVector(Rc::new(v.mapv(|c| -c))),
+                (
+                    Neg,
+                    v @ (Value::TransferFunction(_)
+                    | Value::ContinuousTransferFunction(_)
+                    | Value::Zpk(_)
+                    | Value::StateSpaceModel(_)
+                    | Value::ContinuousStateSpaceModel(_)),
+                ) => arithmetic(ast::BinOp::Mul, &Value::Float(-1.0), &v)?,
+                _ => return Err(Error::TypeError),
             }
         }
         BinOp(op, e1, e2) => {
-            use ast::BinOp::*;
-            let Value::Float(f1) = eval(e1, values, exec_env)? else {
-                return Err(Error::TypeError);
-            };
-            let Value::Float(f2) = eval(e2, values, exec_env)? else {
-                return Err(Error::TypeError);
-            };
-            match op {
-                Add => Value::Float(f1 + f2),
-                Sub => Value::Float(f1 - f2),
-                Mul => Value::Float(f1 * f2),
-                Div => Value::Float(f1 / f2),
-            }
+            let v1 = eval(e1, values, exec_env)?;
+            let v2 = eval(e2, values, exec_env)?;
+            arithmetic(*op, &v1, &v2)?
         }
         FunctionCall {
             function,
@@ -196,17 +696,52 @@ fn eval(
                         let record =
                             result.map_err(|_| Error::Other("Error while parsing csv".into()))?;
                         if i == 0 {
-                            m = Array2::zeros((record.len(), 0));
+                            m = Array2::zeros((0, record.len()));
                         }
-                        m.push(
-                            Axis(0),
-                            Array1::from_iter(record.iter().map(|v| v.parse().unwrap())).view(),
-                        )
-                        .expect("all columns to be of equal length");
+                        let row = record
+                            .iter()
+                            .map(|v| v.trim().parse())
+                            .collect::<Result<Array1<f64>, _>>()
+                            .map_err(|_| {
+                                Error::Other(format!("invalid number in line {}", i + 1).into())
+                            })?;
+                        m.push(Axis(0), row.view()).map_err(|_| {
+                            Error::Other("all lines must have the same number of values".into())
+                        })?;
                     }
                     Value::Matrix(Rc::new(m))
                 }
                 TransferFunction => {
+                    if num_args == 1 {
+                        // conversion of another discrete model
+                        let tf = match eval(&arguments[0], values, exec_env)? {
+                            Value::Zpk(zpk) => zpk.to_transfer_function()?,
+                            Value::StateSpaceModel(ss) => {
+                                DiscreteTransferFunction::from_state_space(&ss)?
+                            }
+                            _ => return Err(Error::TypeError),
+                        };
+                        return Ok(Value::TransferFunction(Rc::new(tf)));
+                    }
+                    if !(2..=3).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(2, 3, num_args));
+                    }
+                    let Value::Vector(num) = eval(&arguments[0], values, exec_env)? else {
+                        return Err(Error::TypeError);
+                    };
+                    let Value::Vector(den) = eval(&arguments[1], values, exec_env)? else {
+                        return Err(Error::TypeError);
+                    };
+                    let mut tf = DiscreteTransferFunction::new((*num).clone(), (*den).clone())?;
+                    if num_args == 3 {
+                        let Value::Float(ts) = eval(&arguments[2], values, exec_env)? else {
+                            return Err(Error::TypeError);
+                        };
+                        tf = tf.with_sample_time(ts)?;
+                    }
+                    Value::TransferFunction(Rc::new(tf))
+                }
+                TfS => {
                     if num_args != 2 {
                         return Err(Error::IncorrectNumberOfArguments(2, num_args));
                     }
@@ -216,65 +751,501 @@ fn eval(
                     let Value::Vector(den) = eval(&arguments[1], values, exec_env)? else {
                         return Err(Error::TypeError);
                     };
-                    let tf = DiscreteTransferFunction::new((*num).clone(), (*den).clone())
-                        .ok_or(Error::Other("Could not construct tf".into()))?;
-                    Value::TransferFunction(Rc::new(tf))
+                    let tf = ContinuousTransferFunction::new((*num).clone(), (*den).clone())?;
+                    Value::ContinuousTransferFunction(Rc::new(tf))
+                }
+                StateSpace => {
+                    // a single system is converted
+                    if num_args == 1 {
+                        return eval(&arguments[0], values, exec_env)?.linearize();
+                    }
+                    if !(4..=5).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(4, 5, num_args));
+                    }
+                    let [a, b, c, d] =
+                        [0, 1, 2, 3].map(|i| eval(&arguments[i], values, exec_env)?.get_matrix());
+                    let (a, b, c, d) = (a?, b?, c?, d?);
+                    // with a sample time the model is discrete
+                    if num_args == 5 {
+                        let Value::Float(ts) = eval(&arguments[4], values, exec_env)? else {
+                            return Err(Error::TypeError);
+                        };
+                        let ss = DiscreteStateSpaceModel::new(a, b, c, d)?.with_sample_time(ts)?;
+                        Value::StateSpaceModel(Rc::new(ss))
+                    } else {
+                        let ss = ContinuousStateSpaceModel::new(a, b, c, d)?;
+                        Value::ContinuousStateSpaceModel(Rc::new(ss))
+                    }
                 }
                 Tf2Ss => {
                     if num_args != 1 {
                         return Err(Error::IncorrectNumberOfArguments(1, num_args));
                     }
-                    let Value::TransferFunction(tf) = eval(&arguments[0], values, exec_env)? else {
+                    match eval(&arguments[0], values, exec_env)? {
+                        Value::TransferFunction(tf) => {
+                            Value::StateSpaceModel(Rc::new(tf.convert_to_state_space()?))
+                        }
+                        Value::ContinuousTransferFunction(tf) => {
+                            Value::ContinuousStateSpaceModel(Rc::new(tf.convert_to_state_space()?))
+                        }
+                        Value::Zpk(zpk) => Value::StateSpaceModel(Rc::new(zpk.to_state_space()?)),
+                        _ => return Err(Error::TypeError),
+                    }
+                }
+                Linearize => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    eval(&arguments[0], values, exec_env)?.linearize()?
+                }
+                ZeroPoleGain => {
+                    if num_args == 1 {
+                        let zpk = match eval(&arguments[0], values, exec_env)? {
+                            Value::TransferFunction(tf) => Zpk::from_transfer_function(&tf),
+                            Value::StateSpaceModel(ss) => Zpk::from_state_space(&ss)?,
+                            _ => return Err(Error::TypeError),
+                        };
+                        return Ok(Value::Zpk(Rc::new(zpk)));
+                    }
+                    if !(3..=4).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(3, 4, num_args));
+                    }
+                    let zeros = eval(&arguments[0], values, exec_env)?.get_roots()?;
+                    let poles = eval(&arguments[1], values, exec_env)?.get_roots()?;
+                    let Value::Float(gain) = eval(&arguments[2], values, exec_env)? else {
                         return Err(Error::TypeError);
                     };
-                    let ss = tf
-                        .convert_to_state_space()
-                        .ok_or(Error::Other("Could not convert to state space".into()))?;
-                    Value::StateSpaceModel(Rc::new(ss))
+                    let mut zpk = Zpk::new(zeros, poles, gain)?;
+                    if num_args == 4 {
+                        let Value::Float(ts) = eval(&arguments[3], values, exec_env)? else {
+                            return Err(Error::TypeError);
+                        };
+                        zpk = zpk.with_sample_time(ts)?;
+                    }
+                    Value::Zpk(Rc::new(zpk))
                 }
-                Step => {
+                Abs | Angle | Real | Imag => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    let part = |c: Complex<f64>| match function {
+                        Abs => c.norm(),
+                        Angle => c.arg(),
+                        Real => c.re,
+                        _ => c.im,
+                    };
+                    let value = eval(&arguments[0], values, exec_env)?;
+                    let (elements, scalar) = value.get_complex_elements()?;
+                    let parts = elements.mapv(part);
+                    if scalar {
+                        Value::Float(parts[0])
+                    } else {
+                        Value::Vector(Rc::new(parts))
+                    }
+                }
+                Conj => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    match eval(&arguments[0], values, exec_env)? {
+                        Value::Complex(c) => Value::Complex(c.conj()),
+                        Value::ComplexVector(v) => {
+                            Value::ComplexVector(Rc::new(v.mapv(|c| c.conj())))
+                        }
+                        real @ (Value::Float(_) | Value::Vector(_)) => real,
+                        _ => return Err(Error::TypeError),
+                    }
+                }
+                Freqresp => {
+                    if num_args != 2 {
+                        return Err(Error::IncorrectNumberOfArguments(2, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?;
+                    let Value::Vector(w) = eval(&arguments[1], values, exec_env)? else {
+                        return Err(Error::TypeError);
+                    };
+                    let response = system.get_frequency_response(&w)?;
+                    if response.nrows() != 1 {
+                        return Err(Error::Other(
+                            "freqresp needs a model with a single input and output".into(),
+                        ));
+                    }
+                    Value::ComplexVector(Rc::new(response.row(0).to_owned()))
+                }
+                Bode => {
+                    if !(1..=2).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(1, 2, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?;
+                    let w = match arguments.get(1) {
+                        Some(argument) => {
+                            let Value::Vector(w) = eval(argument, values, exec_env)? else {
+                                return Err(Error::TypeError);
+                            };
+                            // the plot has a logarithmic frequency axis
+                            if !w.iter().all(|w| w.is_finite() && *w > 0.0) {
+                                return Err(Error::Other("bode needs positive frequencies".into()));
+                            }
+                            (*w).clone()
+                        }
+                        None => system.get_frequency_grid()?,
+                    };
+                    let response = system.get_frequency_response(&w)?;
+                    let magnitude = response.mapv(|c| 20.0 * c.norm().log10());
+                    let mut phase = Array2::zeros(response.raw_dim());
+                    for (mut phase, response) in phase.rows_mut().into_iter().zip(response.rows()) {
+                        phase.assign(&unwrapped_phase(response));
+                    }
+                    Value::FrequencyResponse {
+                        frequency: Rc::new(w),
+                        magnitude: Rc::new(magnitude),
+                        phase: Rc::new(phase),
+                        // margins are only available for single input single output models
+                        margins: if response.nrows() == 1 {
+                            Some(system.get_stability_margins()?)
+                        } else {
+                            None
+                        },
+                    }
+                }
+                Margin => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?;
+                    Value::Margins(system.get_stability_margins()?)
+                }
+                Feedback => {
+                    if !(2..=3).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(2, 3, num_args));
+                    }
+                    let forward = eval(&arguments[0], values, exec_env)?;
+                    let backward = eval(&arguments[1], values, exec_env)?;
+                    // negative feedback by default
+                    let sign = if num_args == 3 {
+                        let Value::Float(sign) = eval(&arguments[2], values, exec_env)? else {
+                            return Err(Error::TypeError);
+                        };
+                        sign
+                    } else {
+                        -1.0
+                    };
+                    // a scalar in the loop feeds back each output to the matching input
+                    let gain = |k, inputs, outputs, _| {
+                        Array2::from_shape_fn(
+                            (inputs, outputs),
+                            |(i, j)| if i == j { k } else { 0.0 },
+                        )
+                    };
+                    match state_space_pair(&forward, &backward, gain)? {
+                        Some(StateSpacePair::Discrete(a, b)) => {
+                            return Ok(Value::StateSpaceModel(Rc::new(a.feedback(&b, sign)?)))
+                        }
+                        Some(StateSpacePair::Continuous(a, b)) => {
+                            return Ok(Value::ContinuousStateSpaceModel(Rc::new(
+                                a.feedback(&b, sign)?,
+                            )))
+                        }
+                        None => {}
+                    }
+                    match transfer_function_pair(&forward, &backward)? {
+                        Some(TransferFunctionPair::Discrete(a, b)) => {
+                            Value::TransferFunction(Rc::new(a.feedback(&b, sign)?))
+                        }
+                        Some(TransferFunctionPair::Continuous(a, b)) => {
+                            Value::ContinuousTransferFunction(Rc::new(a.feedback(&b, sign)?))
+                        }
+                        None => return Err(Error::TypeError),
+                    }
+                }
+                Series | Parallel => {
+                    if num_args != 2 {
+                        return Err(Error::IncorrectNumberOfArguments(2, num_args));
+                    }
+                    let first = eval(&arguments[0], values, exec_env)?;
+                    let second = eval(&arguments[1], values, exec_env)?;
+                    if !first.is_model() && !second.is_model() {
+                        return Err(Error::TypeError);
+                    }
+                    if function == Series {
+                        // the first model acts first, as in second * first
+                        arithmetic(ast::BinOp::Mul, &second, &first)?
+                    } else {
+                        arithmetic(ast::BinOp::Add, &first, &second)?
+                    }
+                }
+                Append | Lft => {
+                    if num_args != 2 {
+                        return Err(Error::IncorrectNumberOfArguments(2, num_args));
+                    }
+                    let first = eval(&arguments[0], values, exec_env)?.to_state_space()?;
+                    let second = eval(&arguments[1], values, exec_env)?.to_state_space()?;
+                    let gain = |k, _, _, _| array![[k]];
+                    match (state_space_pair(&first, &second, gain)?, function) {
+                        (Some(StateSpacePair::Discrete(a, b)), Append) => {
+                            Value::StateSpaceModel(Rc::new(a.append(&b)?))
+                        }
+                        (Some(StateSpacePair::Discrete(a, b)), _) => {
+                            Value::StateSpaceModel(Rc::new(a.lft(&b)?))
+                        }
+                        (Some(StateSpacePair::Continuous(a, b)), Append) => {
+                            Value::ContinuousStateSpaceModel(Rc::new(a.append(&b)?))
+                        }
+                        (Some(StateSpacePair::Continuous(a, b)), _) => {
+                            Value::ContinuousStateSpaceModel(Rc::new(a.lft(&b)?))
+                        }
+                        (None, _) => return Err(Error::TypeError),
+                    }
+                }
+                RLocus => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?;
+                    let (locus, discrete) = match &system {
+                        Value::TransferFunction(tf) => (tf.root_locus(), true),
+                        Value::StateSpaceModel(ss) => (
+                            DiscreteTransferFunction::from_state_space(ss)?.root_locus(),
+                            true,
+                        ),
+                        Value::ContinuousTransferFunction(tf) => (tf.root_locus(), false),
+                        Value::ContinuousStateSpaceModel(ss) => (
+                            ContinuousTransferFunction::from_state_space(ss)?.root_locus(),
+                            false,
+                        ),
+                        Value::Zpk(zpk) => (zpk.to_transfer_function()?.root_locus(), true),
+                        _ => return Err(Error::TypeError),
+                    };
+                    Value::RootLocus {
+                        locus: Rc::new(locus),
+                        poles: Rc::new(Array1::from_vec(system.get_poles()?)),
+                        zeros: Rc::new(Array1::from_vec(system.get_zeros()?)),
+                        discrete,
+                    }
+                }
+                Nyquist => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?;
+                    let transfer_function = system.get_siso_transfer_function()?;
+                    let (_, sample_time) = system.get_roots_and_sample_time()?;
+                    let stability =
+                        nyquist_stability(transfer_function, &system.get_poles()?, sample_time);
+                    // negative frequencies give the mirror image
+                    let w = system.get_frequency_grid()?;
+                    let response = system.get_frequency_response(&w)?.row(0).to_owned();
+                    let curve: Vec<_> = response
+                        .iter()
+                        .rev()
+                        .map(|c| c.conj())
+                        .chain(response.iter().copied())
+                        .filter(|c| c.is_finite())
+                        .collect();
+                    Value::NyquistCurve {
+                        real: Rc::new(curve.iter().map(|c| c.re).collect()),
+                        imag: Rc::new(curve.iter().map(|c| c.im).collect()),
+                        stability,
+                    }
+                }
+                Pole => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    let poles = eval(&arguments[0], values, exec_env)?.get_poles()?;
+                    Value::ComplexVector(Rc::new(Array1::from_vec(poles)))
+                }
+                Zero => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    let zeros = eval(&arguments[0], values, exec_env)?.get_zeros()?;
+                    Value::ComplexVector(Rc::new(Array1::from_vec(zeros)))
+                }
+                PzMap => {
                     if num_args != 1 {
                         return Err(Error::IncorrectNumberOfArguments(1, num_args));
                     }
                     let system = eval(&arguments[0], values, exec_env)?;
-                    let system = match system {
-                        Value::CompoundSystem(s) => s,
-                        other => {
-                            let Ok(block) = other.get_system() else {
+                    let discrete = !matches!(
+                        system,
+                        Value::ContinuousTransferFunction(_) | Value::ContinuousStateSpaceModel(_)
+                    );
+                    let poles = system.get_poles()?;
+                    // zeros are only available for single input single output models
+                    let zeros = system.get_zeros().unwrap_or_default();
+                    Value::PoleZeroMap {
+                        poles: Rc::new(Array1::from_vec(poles)),
+                        zeros: Rc::new(Array1::from_vec(zeros)),
+                        discrete,
+                    }
+                }
+                C2d => {
+                    if !(2..=4).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(2, 4, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?;
+                    let Value::Float(ts) = eval(&arguments[1], values, exec_env)? else {
+                        return Err(Error::TypeError);
+                    };
+                    let method = eval_method(&arguments[2..], values, exec_env)?;
+                    match system {
+                        Value::ContinuousTransferFunction(tf) => {
+                            Value::TransferFunction(Rc::new(tf.discretize(ts, method)?))
+                        }
+                        Value::ContinuousStateSpaceModel(ss) => {
+                            Value::StateSpaceModel(Rc::new(ss.discretize(ts, method)?))
+                        }
+                        _ => return Err(Error::TypeError),
+                    }
+                }
+                D2c => {
+                    if !(1..=3).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(1, 3, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?;
+                    let method = eval_method(&arguments[1..], values, exec_env)?;
+                    match system {
+                        Value::TransferFunction(tf) => {
+                            Value::ContinuousTransferFunction(Rc::new(tf.to_continuous(method)?))
+                        }
+                        Value::StateSpaceModel(ss) => {
+                            Value::ContinuousStateSpaceModel(Rc::new(ss.to_continuous(method)?))
+                        }
+                        _ => return Err(Error::TypeError),
+                    }
+                }
+                Step => {
+                    if num_args != 1 {
+                        return Err(Error::IncorrectNumberOfArguments(1, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?.get_compound_system()?;
+                    let sim = Simulation::new(&system)?;
+                    // step each input separately, one row per input and output
+                    let steps = 36;
+                    // a fixed step size gives the same time for every input
+                    let solver = Solver::Rk4 {
+                        step: sim_interval(&sim) / 20.0,
+                    };
+                    let mut time = sim.time(steps);
+                    let mut output = Array2::zeros((0, 0));
+                    for i in 0..sim.input_size() {
+                        let mut input = Array2::zeros((1, sim.input_size()));
+                        input[(0, i)] = 1.0;
+                        let response = if sim.has_continuous_states() {
+                            let (t, response) =
+                                sim.execute_continuous(input.view(), steps, solver)?;
+                            time = t;
+                            response
+                        } else {
+                            sim.execute(input.view(), steps)?
+                        };
+                        if output.is_empty() {
+                            output = Array2::zeros((0, response.nrows()));
+                        }
+                        for row in response.columns() {
+                            output.push_row(row).unwrap();
+                        }
+                    }
+                    Value::TimeSeries {
+                        time: Rc::new(time),
+                        data: Rc::new(output),
+                    }
+                }
+                Sim => {
+                    if !(2..=4).contains(&num_args) {
+                        return Err(Error::ArgumentCountOutOfRange(2, 4, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?.get_compound_system()?;
+                    let input = eval(&arguments[1], values, exec_env)?.get_input_signal()?;
+                    let steps = if num_args >= 3 {
+                        let Value::Float(steps) = eval(&arguments[2], values, exec_env)? else {
+                            return Err(Error::TypeError);
+                        };
+                        if steps < 0.0 || steps.fract() != 0.0 {
+                            return Err(Error::Other(
+                                "number of steps must be a non-negative integer".into(),
+                            ));
+                        }
+                        steps as usize
+                    } else {
+                        input.nrows()
+                    };
+                    let solver_name = match arguments.get(3) {
+                        Some(argument) => {
+                            let Value::String(name) = eval(argument, values, exec_env)? else {
                                 return Err(Error::TypeError);
                             };
-                            CompoundSystem::new(vec![CompoundSystemComponentDefinition {
-                                block,
-                                name: "".into(),
-                                reads_input_from: ["u".into()].into(),
-                            }])
-                            .map_err(Error::Other)?
-                            .into()
+                            name
                         }
+                        None => "rk45".into(),
+                    };
+                    let sim = Simulation::new(&system)?;
+                    let solver = solver(&solver_name, &sim)?;
+                    let (time, output) = if sim.has_continuous_states() {
+                        sim.execute_continuous(input.view(), steps, solver)?
+                    } else {
+                        (sim.time(steps), sim.execute(input.view(), steps)?)
                     };
-                    let sim = Simulation::new(&system)
-                        .ok_or(Error::Other("could not init sim".into()))?;
-                    let output = sim.execute();
-                    Value::Matrix(Rc::new(output.insert_axis(Axis(0))))
+                    Value::TimeSeries {
+                        time: Rc::new(time),
+                        data: Rc::new(output.reversed_axes()),
+                    }
+                }
+                Trace => {
+                    if num_args < 3 {
+                        return Err(Error::IncorrectNumberOfArguments(3, num_args));
+                    }
+                    let system = eval(&arguments[0], values, exec_env)?.get_compound_system()?;
+                    let input = eval(&arguments[1], values, exec_env)?.get_input_signal()?;
+                    let sim = Simulation::new(&system)?;
+                    let record = if sim.has_continuous_states() {
+                        let solver = solver("rk45", &sim)?;
+                        sim.execute_continuous_recording(input.view(), input.nrows(), solver)?
+                    } else {
+                        sim.execute_recording(input.view(), input.nrows())?
+                    };
+                    // one row per traced signal element
+                    let mut traces = Array2::zeros((0, record.time.len()));
+                    for argument in &arguments[2..] {
+                        let Value::String(name) = eval(argument, values, exec_env)? else {
+                            return Err(Error::TypeError);
+                        };
+                        let signal = record
+                            .signal(&name)
+                            .ok_or(engine::error::Error::UnknownSignal(name.clone()))?;
+                        for column in signal.columns() {
+                            traces.push_row(column).unwrap();
+                        }
+                    }
+                    Value::TimeSeries {
+                        time: Rc::new(record.time),
+                        data: Rc::new(traces),
+                    }
                 }
             }
         }
-        System(items) => {
+        System(system) => {
             let mut sub_systems = Vec::new();
-            for item in items {
+            for item in &system.items {
                 let (inputs, system): (Rc<[Rc<str>]>, SystemBlock) = match &item.rhs {
-                    SystemItemRhs::Difference {
-                        input1_name,
-                        input2_name,
-                    } => (
-                        [input1_name.clone(), input2_name.clone()].into(),
-                        SystemBlock::Difference,
+                    SystemItemRhs::Gain { gain, input_name } => {
+                        let gain = match eval(gain, values, exec_env)? {
+                            Value::Float(k) => Array2::from_elem((1, 1), k),
+                            Value::Matrix(k) => (*k).clone(),
+                            _ => return Err(Error::TypeError),
+                        };
+                        ([input_name.clone()].into(), SystemBlock::Gain(gain.into()))
+                    }
+                    SystemItemRhs::Sum(terms) => (
+                        terms.iter().map(|(_, name)| name.clone()).collect(),
+                        SystemBlock::Sum(terms.iter().map(|(gain, _)| *gain).collect()),
                     ),
                     SystemItemRhs::System {
                         system_name,
-                        input_name,
+                        input_names,
                     } => (
-                        [input_name.clone()].into(),
+                        input_names.clone().into(),
                         values
                             .get(system_name)
                             .ok_or(Error::NullDeref(system_name.clone()))?
@@ -288,10 +1259,850 @@ fn eval(
                     name: item.output_name.clone(),
                 });
             }
-            Value::CompoundSystem(Rc::new(
-                CompoundSystem::new(sub_systems).map_err(Error::Other)?,
-            ))
+            Value::CompoundSystem(Rc::new(CompoundSystem::new(
+                sub_systems,
+                &system.inputs,
+                &system.outputs,
+            )?))
         }
     };
     Ok(value)
 }
+
+/// Two transfer functions of the same kind
+enum TransferFunctionPair {
+    Discrete(DiscreteTransferFunction, DiscreteTransferFunction),
+    Continuous(ContinuousTransferFunction, ContinuousTransferFunction),
+}
+
+/// Both values as transfer functions, where scalars become static gains.
+/// `None` if neither value is a transfer function.
+fn transfer_function_pair(v1: &Value, v2: &Value) -> Result<Option<TransferFunctionPair>, Error> {
+    let is_discrete = |v: &Value| matches!(v, Value::TransferFunction(_) | Value::Zpk(_));
+    let is_continuous = |v: &Value| matches!(v, Value::ContinuousTransferFunction(_));
+    let mixed = || Error::Other("cannot connect continuous and discrete models".into());
+    if is_continuous(v1) || is_continuous(v2) {
+        let convert = |v: &Value| match v {
+            Value::ContinuousTransferFunction(tf) => Ok((**tf).clone()),
+            Value::Float(k) => Ok(ContinuousTransferFunction::new(array![*k], array![1.0])?),
+            v if is_discrete(v) => Err(mixed()),
+            _ => Err(Error::TypeError),
+        };
+        return Ok(Some(TransferFunctionPair::Continuous(
+            convert(v1)?,
+            convert(v2)?,
+        )));
+    }
+    if is_discrete(v1) || is_discrete(v2) {
+        let convert = |v: &Value| match v {
+            Value::TransferFunction(tf) => Ok((**tf).clone()),
+            Value::Zpk(zpk) => Ok(zpk.to_transfer_function()?),
+            Value::Float(k) => Ok(DiscreteTransferFunction::new(array![*k], array![1.0])?),
+            _ => Err(Error::TypeError),
+        };
+        return Ok(Some(TransferFunctionPair::Discrete(
+            convert(v1)?,
+            convert(v2)?,
+        )));
+    }
+    Ok(None)
+}
+
+/// Two state space models of the same kind
+enum StateSpacePair {
+    Discrete(DiscreteStateSpaceModel, DiscreteStateSpaceModel),
+    Continuous(ContinuousStateSpaceModel, ContinuousStateSpaceModel),
+}
+
+/// Both values as state space models if at least one of them is one.
+/// Transfer functions are realized and a scalar `k` becomes the static gain
+/// `gain(k, inputs, outputs, first)` with the numbers of inputs and outputs
+/// of the other model and whether the scalar is the first value.
+fn state_space_pair(
+    v1: &Value,
+    v2: &Value,
+    gain: impl Fn(f64, usize, usize, bool) -> Array2<f64>,
+) -> Result<Option<StateSpacePair>, Error> {
+    let is_state_space = |v: &Value| {
+        matches!(
+            v,
+            Value::StateSpaceModel(_) | Value::ContinuousStateSpaceModel(_)
+        )
+    };
+    if !is_state_space(v1) && !is_state_space(v2) {
+        return Ok(None);
+    }
+    let is_continuous = |v: &Value| {
+        matches!(
+            v,
+            Value::ContinuousTransferFunction(_) | Value::ContinuousStateSpaceModel(_)
+        )
+    };
+    let is_discrete = |v: &Value| {
+        matches!(
+            v,
+            Value::TransferFunction(_) | Value::Zpk(_) | Value::StateSpaceModel(_)
+        )
+    };
+    if (is_continuous(v1) || is_continuous(v2)) && (is_discrete(v1) || is_discrete(v2)) {
+        return Err(Error::Other(
+            "cannot connect continuous and discrete models".into(),
+        ));
+    }
+    let (v1, v2) = (v1.to_state_space()?, v2.to_state_space()?);
+    let discrete_gain = |k, other: &DiscreteStateSpaceModel, first| {
+        let d = gain(k, other.input_size(), other.output_size(), first);
+        DiscreteStateSpaceModel::new(
+            Array2::<f64>::zeros((0, 0)),
+            Array2::<f64>::zeros((0, d.ncols())),
+            Array2::<f64>::zeros((d.nrows(), 0)),
+            d,
+        )
+    };
+    let continuous_gain = |k, other: &ContinuousStateSpaceModel, first| {
+        let d = gain(k, other.input_size(), other.output_size(), first);
+        ContinuousStateSpaceModel::new(
+            Array2::<f64>::zeros((0, 0)),
+            Array2::<f64>::zeros((0, d.ncols())),
+            Array2::<f64>::zeros((d.nrows(), 0)),
+            d,
+        )
+    };
+    use StateSpacePair::*;
+    Ok(Some(match (v1, v2) {
+        (Value::StateSpaceModel(a), Value::StateSpaceModel(b)) => {
+            Discrete((*a).clone(), (*b).clone())
+        }
+        (Value::Float(k), Value::StateSpaceModel(b)) => {
+            Discrete(discrete_gain(k, &b, true)?, (*b).clone())
+        }
+        (Value::StateSpaceModel(a), Value::Float(k)) => {
+            Discrete((*a).clone(), discrete_gain(k, &a, false)?)
+        }
+        (Value::ContinuousStateSpaceModel(a), Value::ContinuousStateSpaceModel(b)) => {
+            Continuous((*a).clone(), (*b).clone())
+        }
+        (Value::Float(k), Value::ContinuousStateSpaceModel(b)) => {
+            Continuous(continuous_gain(k, &b, true)?, (*b).clone())
+        }
+        (Value::ContinuousStateSpaceModel(a), Value::Float(k)) => {
+            Continuous((*a).clone(), continuous_gain(k, &a, false)?)
+        }
+        _ => return Err(Error::TypeError),
+    }))
+}
+
+/// Arithmetic on real and complex scalars and vectors.
+/// Vectors are combined element-wise, scalars with every element.
+fn arithmetic(op: ast::BinOp, v1: &Value, v2: &Value) -> Result<Value, Error> {
+    use ast::BinOp::*;
+    let is_state_space = |v: &Value| {
+        matches!(
+            v,
+            Value::StateSpaceModel(_) | Value::ContinuousStateSpaceModel(_)
+        )
+    };
+    if is_state_space(v1) || is_state_space(v2) {
+        match (op, v2) {
+            (Sub, _) => return arithmetic(Add, v1, &arithmetic(Mul, &Value::Float(-1.0), v2)?),
+            (Div, Value::Float(k)) => return arithmetic(Mul, v1, &Value::Float(1.0 / k)),
+            (Div, _) => {
+                return Err(Error::Other(
+                    "state space models can only be divided by scalars".into(),
+                ))
+            }
+            _ => {}
+        }
+    }
+    // a scalar factor scales each signal, a scalar term is added to every output
+    let gain = |k, inputs, outputs, first| match op {
+        Mul => Array2::eye(if first { outputs } else { inputs }) * k,
+        _ => Array2::from_elem((outputs, inputs), k),
+    };
+    // products are series and sums are parallel connections
+    if let Some(pair) = state_space_pair(v1, v2, gain)? {
+        // in a product the right factor acts first
+        return Ok(match (pair, op) {
+            (StateSpacePair::Discrete(a, b), Mul) => Value::StateSpaceModel(Rc::new(b.series(&a)?)),
+            (StateSpacePair::Discrete(a, b), _) => Value::StateSpaceModel(Rc::new(a.parallel(&b)?)),
+            (StateSpacePair::Continuous(a, b), Mul) => {
+                Value::ContinuousStateSpaceModel(Rc::new(b.series(&a)?))
+            }
+            (StateSpacePair::Continuous(a, b), _) => {
+                Value::ContinuousStateSpaceModel(Rc::new(a.parallel(&b)?))
+            }
+        });
+    }
+    if let Some(pair) = transfer_function_pair(v1, v2)? {
+        return Ok(match pair {
+            TransferFunctionPair::Discrete(a, b) => Value::TransferFunction(Rc::new(match op {
+                Add => a.parallel(&b)?,
+                Sub => a.parallel(
+                    &b.series(&DiscreteTransferFunction::new(array![-1.0], array![1.0])?)?,
+                )?,
+                Mul => a.series(&b)?,
+                Div => a.divide(&b)?,
+            })),
+            TransferFunctionPair::Continuous(a, b) => {
+                Value::ContinuousTransferFunction(Rc::new(match op {
+                    Add => a.parallel(&b)?,
+                    Sub => a.parallel(
+                        &b.series(&ContinuousTransferFunction::new(array![-1.0], array![1.0])?)?,
+                    )?,
+                    Mul => a.series(&b)?,
+                    Div => a.divide(&b)?,
+                }))
+            }
+        });
+    }
+    if let (Value::Float(f1), Value::Float(f2)) = (v1, v2) {
+        return Ok(Value::Float(match op {
+            Add => f1 + f2,
+            Sub => f1 - f2,
+            Mul => f1 * f2,
+            Div => f1 / f2,
+        }));
+    }
+    let (x1, scalar1) = v1.get_complex_elements()?;
+    let (x2, scalar2) = v2.get_complex_elements()?;
+    if !scalar1 && !scalar2 && x1.len() != x2.len() {
+        return Err(Error::Other(
+            format!(
+                "vectors of length {} and {} do not match",
+                x1.len(),
+                x2.len()
+            )
+            .into(),
+        ));
+    }
+    let is_real = |v: &Value| matches!(v, Value::Float(_) | Value::Vector(_));
+    let real = is_real(v1) && is_real(v2);
+    let len = x1.len().max(x2.len());
+    let result = Array1::from_shape_fn(len, |i| {
+        let a = x1[if scalar1 { 0 } else { i }];
+        let b = x2[if scalar2 { 0 } else { i }];
+        match op {
+            Add => a + b,
+            Sub => a - b,
+            Mul => a * b,
+            // real division by zero gives infinity, not NaN
+            Div if real => Complex::new(a.re / b.re, 0.0),
+            Div => a / b,
+        }
+    });
+    Ok(match (real, scalar1 && scalar2) {
+        (true, _) => Value::Vector(Rc::new(result.mapv(|c| c.re))),
+        (false, true) => Value::Complex(result[0]),
+        (false, false) => Value::ComplexVector(Rc::new(result)),
+    })
+}
+
+/// Discretization method from its name and, for tustin, an optional prewarp frequency.
+/// Defaults to zero order hold.
+fn eval_method(
+    arguments: &[Expression],
+    values: &HashMap<Rc<str>, Value>,
+    exec_env: &impl Env,
+) -> Result<Method, Error> {
+    let name = match arguments.first() {
+        Some(argument) => {
+            let Value::String(name) = eval(argument, values, exec_env)? else {
+                return Err(Error::TypeError);
+            };
+            name
+        }
+        None => "zoh".into(),
+    };
+    let prewarp = match arguments.get(1) {
+        Some(argument) => {
+            let Value::Float(w) = eval(argument, values, exec_env)? else {
+                return Err(Error::TypeError);
+            };
+            Some(w)
+        }
+        None => None,
+    };
+    match (&*name, prewarp) {
+        ("zoh", None) => Ok(Method::ZeroOrderHold),
+        ("foh", None) => Ok(Method::FirstOrderHold),
+        ("tustin", prewarp) => Ok(Method::Tustin { prewarp }),
+        ("matched", None) => Ok(Method::Matched),
+        (_, Some(_)) => Err(Error::Other(
+            "a prewarp frequency is only supported by tustin".into(),
+        )),
+        _ => Err(Error::Other(
+            format!("unknown method {name}, expected zoh, foh, tustin or matched").into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::ProgramParser;
+
+    struct TestEnv;
+
+    impl Env for TestEnv {
+        fn read_file(&self, name: &str) -> Option<String> {
+            match name {
+                "u.csv" => Some("0,1,1,0\n".into()),
+                "rd.csv" => Some("1,1\n0,0.5\n".into()),
+                "ryd.csv" => Some("1\n2\n3\n".into()),
+                _ => None,
+            }
+        }
+    }
+
+    fn run(src: &str) -> Vec<Output> {
+        let program = ProgramParser::new().parse(src).unwrap();
+        execute(&program, &TestEnv)
+    }
+
+    /// Plot of signals without sample time, so steps are one second apart
+    fn time_plot(data: Array2<f64>) -> Output {
+        Output::TimePlot {
+            time: Rc::new(Array1::range(0.0, data.ncols() as f64, 1.0)),
+            data: Rc::new(data),
+        }
+    }
+
+    #[test]
+    fn trace_internal_signals() {
+        let out = run(r#"
+            g = tf([0, 1], [1, -1]);
+            sys = { e = u - y; y = g(e); };
+            trace(sys, [1, 1, 1], "e", "y");
+            trace(sys, [1], "x");"#);
+        assert_eq!(
+            out,
+            vec![
+                time_plot(ndarray::array![[1.0, 0.0, 0.0], [0.0, 1.0, 1.0]]),
+                Output::Err(Error::Engine(engine::error::Error::UnknownSignal(
+                    "x".into()
+                ))),
+            ]
+        );
+    }
+
+    #[test]
+    fn linearize_compound_system() {
+        let out = run(r#"
+            g = tf([0, 1], [1, -0.5]);
+            sys = { e = u - y; y = g(e); output y, e; };
+            pole(ss(sys));
+            closed = linearize(sys);
+            bode(closed);
+            gc = tf_s([1], [1, 0]);
+            pole(linearize({ e = u - y; y = gc(e); }));
+            linearize(sys, 1);"#);
+        assert_eq!(out[0], Output::Text("[-0.5]".into()));
+        let Output::BodePlot { magnitude, .. } = &out[1] else {
+            panic!("expected bode plot, got {out:?}");
+        };
+        // one row for each output
+        assert_eq!(magnitude.nrows(), 2);
+        assert_eq!(out[2], Output::Text("[-1]".into()));
+        assert_eq!(out[3], Output::Err(Error::IncorrectNumberOfArguments(1, 2)));
+    }
+
+    #[test]
+    fn step_of_declared_outputs() {
+        let out = run(r#"
+            g = tf([0, 1], [1, -1]);
+            sys = { e = u - y; y = g(e); output y, e; };
+            step(sys);"#);
+        let Output::TimePlot { data, .. } = &out[0] else {
+            panic!("expected plot, got {out:?}");
+        };
+        assert_eq!(data.shape(), &[2, 36]);
+        assert_eq!(data.column(0), ndarray::array![0.0, 1.0]);
+        assert_eq!(data.column(1), ndarray::array![1.0, 0.0]);
+    }
+
+    #[test]
+    fn step_of_continuous_system() {
+        let out = run(r#"
+            g = tf_s([1], [1, 1]);
+            k = tf([2], [1], 0.5);
+            sys = { e = u - y; c = k(e); y = g(c); };
+            step(g);
+            step(sys);"#);
+        let Output::TimePlot { time, data } = &out[0] else {
+            panic!("expected plot, got {out:?}");
+        };
+        // 20 solver steps per second and the start of every second
+        assert_eq!(time.len(), 36 * 21);
+        let i = time.iter().position(|t| *t == 1.0).unwrap();
+        assert!((data[(0, i)] - (1.0 - (-1.0f64).exp())).abs() < 1e-6);
+        let Output::TimePlot { time, data } = &out[1] else {
+            panic!("expected plot, got {out:?}");
+        };
+        assert_eq!(time[time.len() - 1], 18.0);
+        // settles where 2 (1 - y) = y
+        assert!((data[(0, data.ncols() - 1)] - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sim_with_stiff_solver() {
+        let out = run(r#"
+            g = tf_s([1000], [1, 1001, 1000]);
+            sim(g, [1], 5, "stiff");
+            sim(g, [1], 5, "rk45");
+            sim(g, [1], 5, "euler");
+            sim(g);"#);
+        let last = |out: &Output| {
+            let Output::TimePlot { data, .. } = out else {
+                panic!("expected plot, got {out:?}");
+            };
+            data[(0, data.ncols() - 1)]
+        };
+        let expected = 1.0 - (1000.0 * (-5.0f64).exp() - (-5000.0f64).exp()) / 999.0;
+        assert!((last(&out[0]) - expected).abs() < 1e-4);
+        assert!((last(&out[1]) - expected).abs() < 1e-4);
+        assert!(matches!(out[2], Output::Err(Error::Other(_))));
+        assert_eq!(out[3], Output::Err(Error::ArgumentCountOutOfRange(2, 4, 1)));
+        assert_eq!(
+            Error::ArgumentCountOutOfRange(2, 4, 1).to_string(),
+            "expected 2 to 4 arguments, but got 1"
+        );
+    }
+
+    #[test]
+    fn poles_and_zeros() {
+        let out = run(r#"
+            plant = tf([0, 0.5, 0.5], [1, -1.5, 0.7]);
+            pole(plant);
+            zero(tf2ss(plant));
+            p = zpk([], [0.5, 0.25], 2, 0.1);
+            p;
+            pole(p);
+            tf(p);
+            zpk([], [1, 2, 3], 1, 0.1);"#);
+        assert_eq!(
+            out[0],
+            Output::Text("[0.75 + 0.371i, 0.75 - 0.371i]".into())
+        );
+        assert_eq!(out[1], Output::Text("[-1]".into()));
+        assert_eq!(
+            out[2],
+            Output::Text(
+                "         2\n--------------------\n(z - 0.5) (z - 0.25)\nTs = 0.1\n".into()
+            )
+        );
+        assert_eq!(out[3], Output::Text("[0.5, 0.25]".into()));
+        assert_eq!(
+            out[4],
+            Output::Text("          2 z^-2\n--------------------------\n1 - 0.75 z^-1 + 0.125 z^-2\nTs = 0.1\n".into())
+        );
+        assert!(matches!(out[5], Output::Text(_)));
+    }
+
+    #[test]
+    fn complex_arithmetic() {
+        let out = run(r#"
+            c = 1 + 2i;
+            c;
+            c * conj(c);
+            -c / 2i;
+            [1, c] - 1;
+            abs(3 + 4i);
+            angle([1i, -1]);
+            real(pole(tf([1], [1, 0, 1])));
+            imag(c);
+            [1, 2] * [3, 4];
+            [1, 2] + [1, 2, 3];"#);
+        let text = |s: &str| Output::Text(s.into());
+        assert_eq!(out[0], text("1 + 2i"));
+        assert_eq!(out[1], text("5"));
+        assert_eq!(out[2], text("-1 + 0.5i"));
+        assert_eq!(out[3], text("[0, 2i]"));
+        assert_eq!(out[4], text("5"));
+        assert_eq!(out[5], text("[1.571, 3.142]"));
+        assert_eq!(out[6], text("[0, 0]"));
+        assert_eq!(out[7], text("2"));
+        assert_eq!(out[8], text("[3, 8]"));
+        assert!(matches!(out[9], Output::Err(Error::Other(_))));
+    }
+
+    #[test]
+    fn frequency_response() {
+        let out = run(r#"
+            g = tf_s([1], [1, 1]);
+            freqresp(g, [0, 1]);
+            bode(g);
+            bode(tf([0, 1], [1, -0.5], 0.1), [1, 10]);
+            bode(g, [0, 1, 10]);"#);
+        assert_eq!(out[0], Output::Text("[1, 0.5 - 0.5i]".into()));
+        let Output::BodePlot {
+            frequency,
+            magnitude,
+            phase,
+            ..
+        } = &out[1]
+        else {
+            panic!("expected bode plot, got {out:?}");
+        };
+        assert!((frequency[0] - 0.1).abs() < 1e-12);
+        assert!((frequency[frequency.len() - 1] - 10.0).abs() < 1e-9);
+        let i = frequency
+            .iter()
+            .position(|w| (w - 1.0).abs() < 1e-9)
+            .unwrap();
+        assert!((magnitude[(0, i)] + 10.0 * 2f64.log10()).abs() < 1e-9);
+        assert!((phase[(0, i)] + 45.0).abs() < 1e-9);
+        let Output::BodePlot { magnitude, .. } = &out[2] else {
+            panic!("expected bode plot, got {out:?}");
+        };
+        assert_eq!(magnitude.shape(), &[1, 2]);
+        assert_eq!(
+            out[3],
+            Output::Err(Error::Other("bode needs positive frequencies".into()))
+        );
+    }
+
+    #[test]
+    fn margins() {
+        let out = run(r#"
+            l = tf_s([2], [1, 3, 2, 0]);
+            margin(l);
+            bode(l);
+            margin(ss([-1, 0; 0, -2], [1, 0; 0, 1], [1, 0; 0, 1], [0, 0; 0, 0]));"#);
+        let Output::Text(text) = &out[0] else {
+            panic!("expected text, got {out:?}");
+        };
+        assert!(
+            text.starts_with("gain margin: 9.542 dB at 1.414 rad/s\n"),
+            "{text}"
+        );
+        let Output::BodePlot {
+            margins: Some(margins),
+            ..
+        } = &out[1]
+        else {
+            panic!("expected bode plot with margins, got {out:?}");
+        };
+        assert!((margins.gain_margin - 3.0).abs() < 1e-6);
+        assert!((margins.phase_crossover - 2f64.sqrt()).abs() < 1e-6);
+        assert!(margins.phase_margin > 0.0);
+        assert!(matches!(&out[2], Output::Err(e) if e.to_string().contains("single input")));
+    }
+
+    #[test]
+    fn pole_zero_map() {
+        let out = run(r#"
+            pzmap(tf_s([1, 2], [1, 2, 5]));
+            pzmap(tf([1, -0.5], [1, 0.25], 0.1));
+            pzmap(append(tf_s([1], [1, 1]), tf_s([1], [1, 2])));"#);
+        let Output::PoleZeroMap {
+            poles,
+            zeros,
+            discrete,
+        } = &out[0]
+        else {
+            panic!("expected pole zero map, got {out:?}");
+        };
+        assert!(!discrete);
+        assert_eq!(poles.len(), 2);
+        assert!(poles.iter().all(|p| (p.re + 1.0).abs() < 1e-9));
+        assert!((zeros[0].re + 2.0).abs() < 1e-9);
+        assert!(matches!(out[1], Output::PoleZeroMap { discrete: true, .. }));
+        // multiple input multiple output models only show their poles
+        let Output::PoleZeroMap { poles, zeros, .. } = &out[2] else {
+            panic!("expected pole zero map, got {out:?}");
+        };
+        assert_eq!(poles.len(), 2);
+        assert!(zeros.is_empty());
+    }
+
+    #[test]
+    fn transfer_function_algebra() {
+        let out = run(r#"
+            g = tf_s([1], [1, 1]);
+            2 * g;
+            g - g;
+            feedback(g * tf_s([1], [1, 0]), 1);
+            feedback(-g, 1, 1);
+            g / g;
+            plant = tf([0, 0.5, 0.5], [1, -1.5, 0.7]);
+            controller = tf([0.03, 0.01], [1, -1]);
+            feedback(plant * controller, 1);
+            g + plant;"#);
+        assert_eq!(out[0], Output::Text("  2\n-----\ns + 1\n".into()));
+        assert_eq!(
+            out[1],
+            Output::Text("      0\n-------------\ns^2 + 2 s + 1\n".into())
+        );
+        assert_eq!(
+            out[2],
+            Output::Text("     1\n-----------\ns^2 + s + 1\n".into())
+        );
+        assert_eq!(out[3], Output::Text(" -1\n-----\ns + 2\n".into()));
+        assert_eq!(out[4], Output::Text("s + 1\n-----\ns + 1\n".into()));
+        let Output::Text(closed) = &out[5] else {
+            panic!("expected closed loop, got {out:?}");
+        };
+        assert!(closed.ends_with("1 - 2.485 z^-1 + 2.22 z^-2 - 0.695 z^-3\n"));
+        assert!(matches!(out[6], Output::Err(_)));
+    }
+
+    #[test]
+    fn state_space_algebra() {
+        let out = run(r#"
+            g = ss([-1, 0; 0, -2], [1, 0; 0, 1], [1, 0; 0, 1], [0, 0; 0, 0]);
+            pole(feedback(g, 1));
+            pole(lft(g, -1));
+            pole(append(g, tf_s([1], [1, 0])));
+            h = ss([-3, 0; 0, -4], [1, 0; 0, 1], [1, 0; 0, 1], [0, 0; 0, 0]);
+            pole(series(g, 2 * h));
+            g + tf_s([1], [1, 1]);
+            d = ss(0.5, 1, 1, 0, 0.1) - tf([0, 1], [1, -0.2]);
+            pole(d);
+            d * tf_s([1], [1, 1]);
+            g / g;"#);
+        let text = |s: &str| Output::Text(s.into());
+        assert_eq!(out[0], text("[-2, -3]"));
+        assert_eq!(out[1], text("[-1, -3]"));
+        assert_eq!(out[2], text("[-1, -2, 0]"));
+        assert_eq!(out[3], text("[-1, -3, -2, -4]"));
+        assert!(matches!(
+            out[4],
+            Output::Err(Error::Engine(engine::error::Error::DimensionMismatch(_)))
+        ));
+        assert_eq!(out[5], text("[0.5, 0.2]"));
+        assert!(matches!(out[6], Output::Err(_)));
+        assert!(matches!(out[7], Output::Err(_)));
+    }
+
+    #[test]
+    fn root_locus() {
+        let out = run(r#"
+            rlocus(tf_s([1], [1, 2, 0]));
+            rlocus(3);"#);
+        let Output::RootLocusPlot {
+            locus,
+            poles,
+            discrete,
+            ..
+        } = &out[0]
+        else {
+            panic!("expected root locus, got {out:?}");
+        };
+        assert!(!discrete);
+        assert_eq!(locus.branches.ncols(), poles.len());
+        assert_eq!(locus.gains[0], 0.0);
+        assert!(matches!(out[1], Output::Err(_)));
+    }
+
+    #[test]
+    fn nyquist() {
+        let out = run(r#"
+            nyquist(tf_s([2], [1, -1]));
+            nyquist(ss([-1, 0; 0, -2], [1, 0; 0, 1], [1, 0; 0, 1], [0, 0; 0, 0]));"#);
+        let Output::NyquistPlot {
+            real,
+            imag,
+            stability,
+        } = &out[0]
+        else {
+            panic!("expected nyquist plot, got {out:?}");
+        };
+        assert_eq!(real.len(), imag.len());
+        assert!((imag[0] + imag[imag.len() - 1]).abs() < 1e-12);
+        assert_eq!(stability.encirclements, 1);
+        assert_eq!(stability.unstable_poles, 1);
+        assert!(matches!(out[1], Output::Err(_)));
+    }
+
+    #[test]
+    fn named_inputs() {
+        let out = run(r#"
+            g = tf([2], [1]);
+            sys = { input r, d; e = r - d; y = g(e); output e, y; };
+            sim(sys, load("rd.csv"));
+            step(sys);"#);
+        assert_eq!(out[0], time_plot(ndarray::array![[1.0, 0.5], [2.0, 1.0]]));
+        let Output::TimePlot { data, .. } = &out[1] else {
+            panic!("expected plot, got {out:?}");
+        };
+        assert_eq!(data.column(0), ndarray::array![1.0, 2.0, -1.0, -2.0]);
+    }
+
+    #[test]
+    fn keywords_as_names() {
+        let out = run(r#"
+            output = tf([2], [1]);
+            sys = { output = output(u); y = 3 * output; output output, y; };
+            sim(sys, [1]);
+            input = tf([4], [1]);
+            sim({ input input; y = input(input); }, [1]);
+            sim({ input = output(u); y = input(input); }, [1]);"#);
+        assert_eq!(out[0], time_plot(ndarray::array![[2.0], [6.0]]));
+        assert_eq!(out[1], time_plot(ndarray::array![[4.0]]));
+        assert_eq!(out[2], time_plot(ndarray::array![[8.0]]));
+    }
+
+    #[test]
+    fn nested_systems() {
+        let out = run(r#"
+            g = tf([0, 1], [1, -1]);
+            inner = { e = u - y; y = g(e); };
+            two = { input a, b; sa = g(a); sb = g(b); output sa, sb; };
+            outer = { r = inner(u); s = two(u, r); output r, s; };
+            sim(outer, [1, 1, 1]);
+            trace(outer, [1, 1, 1], "r.e");"#);
+        assert_eq!(
+            out,
+            vec![
+                time_plot(ndarray::array![
+                    [0.0, 1.0, 1.0],
+                    [0.0, 1.0, 2.0],
+                    [0.0, 0.0, 1.0]
+                ]),
+                time_plot(ndarray::array![[1.0, 0.0, 0.0]]),
+            ]
+        );
+    }
+
+    #[test]
+    fn sum_blocks() {
+        let out = run(r#"
+            sys = {
+                input r, y, d;
+                e = r - y + d;
+                v = 2*e - 0.5 * d;
+                w = -r + -2*y;
+                output e, v, w;
+            };
+            sim(sys, load("ryd.csv"));"#);
+        assert_eq!(out, vec![time_plot(ndarray::array![[2.0], [2.5], [-5.0]])]);
+    }
+
+    #[test]
+    fn gain_blocks() {
+        let out = run(r#"
+            k = 3;
+            sys = { a = 2.5 * u; b = k * a; c = -1 * b; output a, b, c; };
+            sim(sys, [2]);"#);
+        assert_eq!(
+            out,
+            vec![time_plot(ndarray::array![[5.0], [15.0], [-15.0]])]
+        );
+    }
+
+    #[test]
+    fn engine_errors_are_reported() {
+        let out = run(r#"
+            tf([1], [0, 1]);
+            tf([], [1]);
+            step({ e = u - y; y = 2 * e; });"#);
+        assert_eq!(out.len(), 3);
+        assert!(matches!(
+            out[0],
+            Output::Err(Error::Engine(engine::error::Error::InvalidModel(_)))
+        ));
+        assert!(matches!(
+            out[1],
+            Output::Err(Error::Engine(engine::error::Error::InvalidModel(_)))
+        ));
+        let Output::Err(e) = &out[2] else {
+            panic!("expected error, got {out:?}");
+        };
+        assert_eq!(e.to_string(), "algebraic loop: e -> y -> e");
+    }
+
+    #[test]
+    fn continuous_models() {
+        let out = run(r#"
+            g = tf_s([1], [1, 2]);
+            g;
+            tf2ss(g);
+            ss([0, 1; -2, -3], [0; 1], [1, 0], 0);
+            ss([0, 1; -2], [0; 1], [1, 0], 0);
+            ss([0, 1; -2, -3], [0, 1], [1, 0], 0);"#);
+        assert_eq!(out[0], Output::Text("  1\n-----\ns + 2\n".into()));
+        let Output::Text(ss) = &out[1] else {
+            panic!("expected text, got {out:?}");
+        };
+        assert!(ss.starts_with("A:"));
+        assert!(matches!(out[2], Output::Text(_)));
+        assert!(matches!(out[3], Output::Err(Error::Other(_))));
+        assert!(matches!(
+            out[4],
+            Output::Err(Error::Engine(engine::error::Error::DimensionMismatch(_)))
+        ));
+    }
+
+    #[test]
+    fn discretization() {
+        let out = run(r#"
+            g = tf_s([1], [1, 0]);
+            gd = c2d(g, 0.1, "tustin");
+            sim(gd, [1, 1, 1]);
+            d2c(gd, "tustin");
+            c2d(g, 0.1, "euler");
+            c2d(gd, 0.1);"#);
+        let Output::TimePlot { data, .. } = &out[0] else {
+            panic!("expected plot, got {out:?}");
+        };
+        assert!(data.abs_diff_eq(&ndarray::array![[0.05, 0.15, 0.25]], 1e-12));
+        assert_eq!(out[1], Output::Text("1\n-\ns\n".into()));
+        assert!(matches!(out[2], Output::Err(Error::Other(_))));
+        assert_eq!(out[3], Output::Err(Error::TypeError));
+    }
+
+    #[test]
+    fn sample_times() {
+        let out = run(r#"
+            g = tf([0, 1], [1, -1], 0.5);
+            g;
+            sim(g, [1, 1, 1]);
+            ss(1, 1, 1, 0, 0.5);
+            d2c(tf([0, 1], [1, -1]));
+            tf([1], [1], 0);
+            h = tf([0, 1], [1, -1], 0.2);
+            sim({ a = h(u); b = g(a); output a, b; }, [1], 11);"#);
+        assert_eq!(
+            out[0],
+            Output::Text("  z^-1\n--------\n1 - z^-1\nTs = 0.5\n".into())
+        );
+        assert_eq!(
+            out[1],
+            Output::TimePlot {
+                time: Rc::new(ndarray::array![0.0, 0.5, 1.0]),
+                data: Rc::new(ndarray::array![[0.0, 1.0, 2.0]]),
+            }
+        );
+        assert!(matches!(&out[2], Output::Text(t) if t.ends_with("Ts: 0.5 ")));
+        for e in &out[3..5] {
+            assert!(matches!(
+                e,
+                Output::Err(Error::Engine(engine::error::Error::InvalidModel(_)))
+            ));
+        }
+        // h runs every second and g every fifth step of the base rate
+        let Output::TimePlot { time, data } = &out[5] else {
+            panic!("expected plot, got {out:?}");
+        };
+        assert!(time.abs_diff_eq(&Array1::linspace(0.0, 1.0, 11), 1e-12));
+        assert_eq!(
+            **data,
+            ndarray::array![
+                [0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 5.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0]
+            ]
+        );
+    }
+
+    #[test]
+    fn sim_with_recorded_input() {
+        let out = run(r#"g = tf([2], [1]); sim(g, load("u.csv")); sim(g, [1, 2], 3);"#);
+        assert_eq!(
+            out,
+            vec![
+                time_plot(ndarray::array![[0.0, 2.0, 2.0, 0.0]]),
+                time_plot(ndarray::array![[2.0, 4.0, 4.0]]),
+            ]
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use lalrpop_util::lalrpop_mod;
 
-lalrpop_mod!(pub grammar);
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    pub grammar
+);
 pub mod ast;
 pub mod execution;
 
@@ -29,4 +32,18 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn complex_literal() {
+        let expression = grammar::ExpressionParser::new().parse("1 - .5i").unwrap();
+        use ast::Expression::*;
+        assert_eq!(
+            expression,
+            BinOp(
+                ast::BinOp::Sub,
+                FloatLiteral(1.0).into(),
+                ImaginaryLiteral(0.5).into()
+            )
+        );
+    }
 }